@@ -0,0 +1,228 @@
+// Basic blocks and control-flow graphs of binasm procedures
+
+use super::procedure::{procedures, Procedure};
+use super::*;
+use crate::st::SymbolTable;
+
+// Local label an instruction branches to, if any
+fn branch_label(args: &ArgFormat) -> Option<i32> {
+    match args {
+        ArgFormat::fl { symno }
+        | ArgFormat::frl { symno, .. }
+        | ArgFormat::frrl { symno, .. }
+        | ArgFormat::fril { symno, .. }
+            if *symno < 0 =>
+        {
+            Some(*symno)
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct BasicBlock {
+    // Range of record indices in the procedure, end exclusive
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<usize>,
+}
+
+pub struct Cfg {
+    pub symno: i32,
+    pub records: Vec<Record>,
+    pub blocks: Vec<BasicBlock>,
+}
+
+// Splits a procedure into basic blocks at label definitions and after control transfers, and links
+// them through their local label numbers. A transfer's delay slot, if the decoder marked one,
+// stays in the same block, except that of a likely branch: it only runs when the branch is taken,
+// so it gets a block of its own on the taken edge.
+pub fn build_cfg(proc: &Procedure) -> Cfg {
+    let records = &proc.records;
+    let mut leaders = vec![false; records.len() + 1];
+    // Block-ending transfer, with its branch target, indexed by the record that ends the block
+    let mut exits: Vec<Option<(Transfer, Option<i32>)>> = vec![None; records.len()];
+    let mut pending: Option<(Transfer, Option<i32>)> = None;
+    // Likely branches whose delay slot was split off into the next block
+    let mut likely = vec![false; records.len()];
+    // Labels only start a new block once the current one has instructions, so the procedure's own
+    // label stays with the directives before it
+    let mut has_code = false;
+
+    leaders[0] = true;
+    for (i, record) in records.iter().enumerate() {
+        if leaders[i] {
+            has_code = false;
+        }
+        match &record.asm {
+            Asm::ilabel { .. } | Asm::ilab { .. } if has_code => {
+                leaders[i] = true;
+                has_code = false;
+            }
+            Asm::iocode { op, args } => {
+                has_code = true;
+                if record.delay_slot {
+                    if let Some(exit) = pending.take() {
+                        exits[i] = Some(exit);
+                        leaders[i + 1] = true;
+                    }
+                } else if let Some(kind) = transfer(*op) {
+                    let exit = (kind, branch_label(args));
+                    if record.set.reorder {
+                        exits[i] = Some(exit);
+                        leaders[i + 1] = true;
+                    } else if op_info(*op).likely {
+                        // The delay slot block only runs on the way to the target
+                        likely[i] = true;
+                        leaders[i + 1] = true;
+                        pending = Some(match exit {
+                            (Transfer::Conditional, label) => (Transfer::Unconditional, label),
+                            exit => exit,
+                        });
+                    } else {
+                        pending = Some(exit);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let starts: Vec<usize> = (0..records.len()).filter(|i| leaders[*i]).collect();
+    let mut blocks: Vec<BasicBlock> = starts
+        .iter()
+        .enumerate()
+        .map(|(b, start)| BasicBlock {
+            start: *start,
+            end: starts.get(b + 1).copied().unwrap_or(records.len()),
+            successors: Vec::new(),
+        })
+        .collect();
+
+    let label_block = |label: i32| {
+        blocks.iter().position(|block| {
+            records[block.start..block.end].iter().any(|record| {
+                matches!(record.asm, Asm::ilabel { symno } | Asm::ilab { symno } if symno == label)
+            })
+        })
+    };
+    let successors: Vec<Vec<usize>> = (0..blocks.len())
+        .map(|b| {
+            let block = &blocks[b];
+            let exit = (block.start..block.end).rev().find_map(|i| exits[i]);
+            let fallthrough = (b + 1 < blocks.len()).then_some(b + 1);
+            let mut succ = Vec::new();
+            if block.end > block.start && likely[block.end - 1] {
+                // Taken: into the delay slot block. Not taken: past it.
+                succ.extend(fallthrough);
+                succ.extend((b + 2 < blocks.len()).then_some(b + 2));
+                return succ;
+            }
+            match exit {
+                None | Some((Transfer::Call, _)) => succ.extend(fallthrough),
+                Some((Transfer::Conditional, label)) => {
+                    succ.extend(label.and_then(label_block));
+                    succ.extend(fallthrough);
+                }
+                Some((Transfer::Unconditional, label)) => succ.extend(label.and_then(label_block)),
+                Some((Transfer::Indirect, _)) => {}
+            }
+            succ.dedup();
+            succ
+        })
+        .collect();
+    for (block, succ) in blocks.iter_mut().zip(successors) {
+        block.successors = succ;
+    }
+
+    Cfg {
+        symno: proc.symno,
+        records: records.clone(),
+        blocks,
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\t', " ")
+}
+
+impl Cfg {
+    // Graphviz digraph with one node per basic block
+    pub fn to_dot(&self, name: &str, st: Option<&SymbolTable>) -> String {
+        let mut dot = format!("digraph \"{}\" {{\n", escape_dot(name));
+        dot.push_str("\tnode [shape=box, fontname=\"monospace\"];\n");
+        for (b, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for record in &self.records[block.start..block.end] {
+                let line = record.asm.to_string();
+                let line = match st {
+                    Some(st) => replace_symbol_names(&line, |n| st.dense_name(n as u32)),
+                    None => line,
+                };
+                label.push_str(&escape_dot(line.trim_start()));
+                label.push_str("\\l");
+            }
+            dot.push_str(&format!("\tb{b} [label=\"{label}\"];\n"));
+            for succ in &block.successors {
+                dot.push_str(&format!("\tb{b} -> b{succ};\n"));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Prints a Graphviz digraph for each procedure in a binasm file
+pub fn print_cfgs(bytes: &[u8], st: Option<&SymbolTable>) {
+    for proc in procedures(&read_records(bytes)) {
+        let cfg = build_cfg(&proc);
+        let name = st
+            .and_then(|st| st.dense_name(cfg.symno as u32))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("SYM_{}", cfg.symno));
+        print!("{}", cfg.to_dot(&name, st));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::*;
+    use super::*;
+    use asmcode::*;
+    use register::*;
+
+    #[test]
+    fn links_blocks_through_labels() {
+        let file = Binasm::new(Endian::Big)
+            .ent(1)
+            .label(1)
+            .frame(0, xr29, xr31)
+            .set(set_value::set_noreorder)
+            .ins(zbeq, rrl(xr4, xr0, -1))
+            .ins(zaddu, rrr(xr2, xr4, xr5))
+            .ins(zaddiu, rri(xr2, xr2, 1))
+            .label(-1)
+            .ins(zjr, r(xr31))
+            .ins(znop, none())
+            .end(1);
+        let cfg = build_cfg(&procedures(&file.records())[0]);
+        let blocks: Vec<_> = cfg
+            .blocks
+            .iter()
+            .map(|block| (block.start, block.end, block.successors.clone()))
+            .collect();
+        // The branch keeps its delay slot, the label starts the block both paths reach, and the .end
+        // after the return is left on its own
+        assert_eq!(
+            blocks,
+            [
+                (0, 6, vec![2, 1]),
+                (6, 7, vec![2]),
+                (7, 10, vec![]),
+                (10, 11, vec![])
+            ]
+        );
+    }
+}
@@ -1,7 +1,7 @@
 #![allow(non_camel_case_types)]
 
 use std::fmt;
-use strum_macros::{Display, EnumIter, FromRepr};
+use strum_macros::{Display, EnumIter, FromRepr, IntoStaticStr};
 
 #[derive(Clone, Copy, Debug, PartialEq, EnumIter, FromRepr)]
 pub enum register {
@@ -83,6 +83,7 @@ pub enum register {
     xnoreg,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum RegNaming {
     Numeric,
@@ -270,6 +271,8 @@ impl fmt::Display for register {
     }
 }
 
+#[allow(dead_code)]
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Copy, Debug, Display, PartialEq, EnumIter, FromRepr)]
 pub enum GPR {
     zero,
@@ -307,7 +310,7 @@ pub enum GPR {
 }
 
 // asmcodes specify the assembler instructions
-#[derive(Clone, Copy, PartialEq, EnumIter, FromRepr)]
+#[derive(Clone, Copy, PartialEq, EnumIter, FromRepr, IntoStaticStr)]
 pub enum asmcode {
     zabs,
     zadd,
@@ -1342,6 +1345,7 @@ impl fmt::Display for opt_type {
     }
 }
 
+#[allow(dead_code)]
 pub enum opt_arg_type {
     opt_none,
     opt_int,
@@ -0,0 +1,496 @@
+// Procedure-by-procedure comparison of two binasm files. Symbol and local label numbers are
+// renumbered by order of first appearance so that unrelated renumbering does not show up as a
+// difference.
+
+use std::collections::HashMap;
+
+use super::procedure::{procedures, Procedure};
+use super::*;
+use crate::st::SymbolTable;
+
+const CONTEXT_LINES: usize = 3;
+
+struct Line {
+    text: String,
+    asm: Asm,
+}
+
+// Names of normalized symbols, shared between the two files so the same symbol gets the same
+// number in both
+struct SymbolNames {
+    names: Vec<String>,
+}
+
+impl SymbolNames {
+    fn intern(&mut self, name: String) -> i32 {
+        let idx = match self.names.iter().position(|n| *n == name) {
+            Some(idx) => idx,
+            None => {
+                self.names.push(name);
+                self.names.len() - 1
+            }
+        };
+        idx as i32 + 1
+    }
+
+    // Replaces each SYM_n in a normalized line by the name of symbol n
+    fn render(&self, text: &str) -> String {
+        replace_symbol_names(text, |n| {
+            self.names.get(n.checked_sub(1)?).map(|s| s.as_str())
+        })
+    }
+}
+
+fn procedure_name(proc: &Procedure, st: Option<&SymbolTable>) -> String {
+    st.and_then(|st| st.dense_name(proc.symno as u32))
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("SYM_{}", proc.symno))
+}
+
+fn normalize_procedure(
+    proc: &Procedure,
+    st: Option<&SymbolTable>,
+    names: &mut SymbolNames,
+) -> Vec<Line> {
+    let mut labels: HashMap<i32, i32> = HashMap::new();
+    let mut symbols: HashMap<i32, i32> = HashMap::new();
+    let mut renumber = |symno: i32| {
+        if symno < 0 {
+            let next = labels.len() as i32 + 1;
+            -*labels.entry(symno).or_insert(next)
+        } else {
+            let next = symbols.len() + 1;
+            let key = match st.and_then(|st| st.dense_name(symno as u32)) {
+                Some(name) => name.to_string(),
+                None => format!("SYM_{}", *symbols.entry(symno).or_insert(next as i32)),
+            };
+            names.intern(key)
+        }
+    };
+
+    proc.records
+        .iter()
+        .filter(|record| !matches!(record.asm, Asm::iloc { .. }))
+        .map(|record| {
+            let asm = record.asm.map_symnos(&mut renumber);
+            Line {
+                text: asm.to_string(),
+                asm,
+            }
+        })
+        .collect()
+}
+
+// Registers and immediates of an instruction's operands
+fn operand_parts(args: &ArgFormat) -> (Vec<register>, Vec<i32>) {
+    match args {
+        ArgFormat::frob {
+            reg, offset, base, ..
+        } => (vec![*reg, *base], vec![*offset as i32]),
+        ArgFormat::fra { reg, offset, .. } => (vec![*reg], vec![*offset]),
+        ArgFormat::fri { reg, immediate, .. } => (vec![*reg], vec![*immediate]),
+        ArgFormat::frrr { reg1, reg2, reg3 } => (vec![*reg1, *reg2, *reg3], vec![]),
+        ArgFormat::frri {
+            reg1,
+            reg2,
+            immediate,
+            ..
+        } => (vec![*reg1, *reg2], vec![*immediate]),
+        ArgFormat::frr { reg1, reg2 } => (vec![*reg1, *reg2], vec![]),
+        ArgFormat::fa {
+            base, immediate, ..
+        } => (vec![*base], vec![*immediate]),
+        ArgFormat::fr { reg } => (vec![*reg], vec![]),
+        ArgFormat::frrl { reg1, reg2, .. } => (vec![*reg1, *reg2], vec![]),
+        ArgFormat::frl { reg, .. } => (vec![*reg], vec![]),
+        ArgFormat::fril { reg, immediate, .. } => (vec![*reg], vec![*immediate]),
+        ArgFormat::fi { immediate } => (vec![], vec![*immediate]),
+        ArgFormat::frrrr {
+            reg1,
+            reg2,
+            reg3,
+            reg4,
+        } => (vec![*reg1, *reg2, *reg3, *reg4], vec![]),
+        ArgFormat::fl { .. } | ArgFormat::forrr | ArgFormat::foa => (vec![], vec![]),
+    }
+}
+
+// Which parts of an instruction changed, for annotating a replaced line
+fn describe_change(old: &Asm, new: &Asm) -> Option<String> {
+    let (
+        Asm::iocode {
+            op: old_op,
+            args: old_args,
+        },
+        Asm::iocode {
+            op: new_op,
+            args: new_args,
+        },
+    ) = (old, new)
+    else {
+        return None;
+    };
+
+    let mut parts = Vec::new();
+    if old_op != new_op {
+        parts.push("opcode");
+    }
+    let (old_regs, old_imms) = operand_parts(old_args);
+    let (new_regs, new_imms) = operand_parts(new_args);
+    if old_regs != new_regs {
+        parts.push("register");
+    }
+    if old_imms != new_imms {
+        parts.push("immediate");
+    }
+    if parts.is_empty() {
+        parts.push("symbol");
+    }
+    Some(parts.join(", "))
+}
+
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// Lengths of the longest common subsequences of `a` and each prefix of `b`, or with `reverse`,
+// of `a` and each suffix of `b` matched from the end
+fn lcs_lengths(a: &[Line], b: &[Line], reverse: bool) -> Vec<u32> {
+    let at = |k: usize| if reverse { &a[a.len() - 1 - k] } else { &a[k] };
+    let bt = |k: usize| if reverse { &b[b.len() - 1 - k] } else { &b[k] };
+    let mut row = vec![0u32; b.len() + 1];
+    for i in 0..a.len() {
+        let mut diagonal = 0;
+        for j in 0..b.len() {
+            let above = row[j + 1];
+            row[j + 1] = if at(i).text == bt(j).text {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row
+}
+
+// Hirschberg's divide-and-conquer LCS, which needs space linear in the length of the inputs.
+// `a_off` and `b_off` are the positions of the slices in the whole procedures.
+fn diff_range(a: &[Line], b: &[Line], a_off: usize, b_off: usize, edits: &mut Vec<Edit>) {
+    if a.is_empty() {
+        edits.extend((0..b.len()).map(|j| Edit::Insert(b_off + j)));
+        return;
+    }
+    if b.is_empty() {
+        edits.extend((0..a.len()).map(|i| Edit::Delete(a_off + i)));
+        return;
+    }
+    if a.len() == 1 {
+        match b.iter().position(|line| line.text == a[0].text) {
+            Some(j) => {
+                edits.extend((0..j).map(|k| Edit::Insert(b_off + k)));
+                edits.push(Edit::Equal(a_off, b_off + j));
+                edits.extend((j + 1..b.len()).map(|k| Edit::Insert(b_off + k)));
+            }
+            None => {
+                edits.push(Edit::Delete(a_off));
+                edits.extend((0..b.len()).map(|k| Edit::Insert(b_off + k)));
+            }
+        }
+        return;
+    }
+
+    let mid = a.len() / 2;
+    let front = lcs_lengths(&a[..mid], b, false);
+    let back = lcs_lengths(&a[mid..], b, true);
+    let split = (0..=b.len())
+        .max_by_key(|&k| (front[k] + back[b.len() - k], std::cmp::Reverse(k)))
+        .unwrap();
+    diff_range(&a[..mid], &b[..split], a_off, b_off, edits);
+    diff_range(&a[mid..], &b[split..], a_off + mid, b_off + split, edits);
+}
+
+// Longest-common-subsequence diff of two sequences of lines. Lines common to the start and end are
+// matched directly before the rest is diffed.
+fn diff_lines(a: &[Line], b: &[Line]) -> Vec<Edit> {
+    let prefix = a
+        .iter()
+        .zip(b)
+        .take_while(|(x, y)| x.text == y.text)
+        .count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x.text == y.text)
+        .count();
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|k| Edit::Equal(k, k)).collect();
+    diff_range(
+        &a[prefix..a.len() - suffix],
+        &b[prefix..b.len() - suffix],
+        prefix,
+        prefix,
+        &mut edits,
+    );
+    edits.extend((0..suffix).map(|k| Edit::Equal(a.len() - suffix + k, b.len() - suffix + k)));
+    edits
+}
+
+fn print_hunk(edits: &[Edit], a: &[Line], b: &[Line], names: &SymbolNames) {
+    let a_start = edits.iter().find_map(|e| match e {
+        Edit::Equal(i, _) | Edit::Delete(i) => Some(*i),
+        Edit::Insert(_) => None,
+    });
+    let b_start = edits.iter().find_map(|e| match e {
+        Edit::Equal(_, j) | Edit::Insert(j) => Some(*j),
+        Edit::Delete(_) => None,
+    });
+    let a_len = edits
+        .iter()
+        .filter(|e| !matches!(e, Edit::Insert(_)))
+        .count();
+    let b_len = edits
+        .iter()
+        .filter(|e| !matches!(e, Edit::Delete(_)))
+        .count();
+    println!(
+        "@@ -{},{} +{},{} @@",
+        a_start.map_or(0, |i| i + 1),
+        a_len,
+        b_start.map_or(0, |j| j + 1),
+        b_len
+    );
+
+    let mut k = 0;
+    while k < edits.len() {
+        if let Edit::Equal(i, _) = edits[k] {
+            println!(" {}", names.render(&a[i].text));
+            k += 1;
+            continue;
+        }
+        // A run of changes: deletions and insertions are paired up in order to describe what
+        // changed in each replaced instruction
+        let end = edits[k..]
+            .iter()
+            .position(|e| matches!(e, Edit::Equal(..)))
+            .map_or(edits.len(), |p| k + p);
+        let deleted: Vec<usize> = edits[k..end]
+            .iter()
+            .filter_map(|e| match e {
+                Edit::Delete(i) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        let inserted: Vec<usize> = edits[k..end]
+            .iter()
+            .filter_map(|e| match e {
+                Edit::Insert(j) => Some(*j),
+                _ => None,
+            })
+            .collect();
+        for i in &deleted {
+            println!("-{}", names.render(&a[*i].text));
+        }
+        for (n, j) in inserted.iter().enumerate() {
+            let note = deleted
+                .get(n)
+                .and_then(|i| describe_change(&a[*i].asm, &b[*j].asm));
+            match note {
+                Some(note) => println!("+{}\t# {note}", names.render(&b[*j].text)),
+                None => println!("+{}", names.render(&b[*j].text)),
+            }
+        }
+        k = end;
+    }
+}
+
+// Prints a unified diff of one pair of procedures. Returns whether they differ.
+fn diff_procedure(a_name: &str, b_name: &str, a: &[Line], b: &[Line], names: &SymbolNames) -> bool {
+    let edits = diff_lines(a, b);
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Equal(..)))
+        .map(|(k, _)| k)
+        .collect();
+    if changed.is_empty() {
+        return false;
+    }
+
+    println!("--- a/{a_name}");
+    println!("+++ b/{b_name}");
+    // Group changes whose context would overlap into the same hunk
+    let mut start = 0;
+    while start < changed.len() {
+        let mut end = start;
+        while end + 1 < changed.len() && changed[end + 1] - changed[end] <= 2 * CONTEXT_LINES {
+            end += 1;
+        }
+        let from = changed[start].saturating_sub(CONTEXT_LINES);
+        let to = (changed[end] + CONTEXT_LINES + 1).min(edits.len());
+        print_hunk(&edits[from..to], a, b, names);
+        start = end + 1;
+    }
+    true
+}
+
+// Pairs the procedures of two files: by name with symbol tables for both, otherwise by order of
+// appearance, since symbol numbers are assigned per file and say nothing on their own. Procedures
+// the tables have no name for are left unpaired.
+fn pair_procedures<'p>(
+    a_procs: &'p [Procedure],
+    b_procs: &'p [Procedure],
+    a_st: Option<&SymbolTable>,
+    b_st: Option<&SymbolTable>,
+) -> Vec<(Option<&'p Procedure>, Option<&'p Procedure>)> {
+    let (Some(a_st), Some(b_st)) = (a_st, b_st) else {
+        return (0..a_procs.len().max(b_procs.len()))
+            .map(|k| (a_procs.get(k), b_procs.get(k)))
+            .collect();
+    };
+    let a_name = |proc: &Procedure| a_st.dense_name(proc.symno as u32);
+    let b_name = |proc: &Procedure| b_st.dense_name(proc.symno as u32);
+
+    let mut pairs = Vec::new();
+    for a in a_procs {
+        let b = a_name(a).and_then(|name| b_procs.iter().find(|b| b_name(b) == Some(name)));
+        pairs.push((Some(a), b));
+    }
+    for b in b_procs {
+        let paired = b_name(b).is_some_and(|name| a_procs.iter().any(|a| a_name(a) == Some(name)));
+        if !paired {
+            pairs.push((None, Some(b)));
+        }
+    }
+    pairs
+}
+
+// Compares two binasm files procedure by procedure, pairing them as pair_procedures does. Returns
+// the number of procedures that differ or appear in only one file.
+pub fn diff_binasm(
+    a_bytes: &[u8],
+    b_bytes: &[u8],
+    a_st: Option<&SymbolTable>,
+    b_st: Option<&SymbolTable>,
+) -> usize {
+    let a_procs = procedures(&read_records(a_bytes));
+    let b_procs = procedures(&read_records(b_bytes));
+    // Without both tables names cannot be compared, so neither file's is used for matching
+    let (a_key, b_key) = match (a_st, b_st) {
+        (Some(_), Some(_)) => (a_st, b_st),
+        _ => (None, None),
+    };
+    let mut names = SymbolNames { names: Vec::new() };
+    let mut differences = 0;
+
+    let pairs = pair_procedures(&a_procs, &b_procs, a_key, b_key);
+    for pair in pairs {
+        match pair {
+            (Some(a), Some(b)) => {
+                let a_lines = normalize_procedure(a, a_key, &mut names);
+                let b_lines = normalize_procedure(b, b_key, &mut names);
+                let a_name = procedure_name(a, a_st);
+                let b_name = procedure_name(b, b_st);
+                if diff_procedure(&a_name, &b_name, &a_lines, &b_lines, &names) {
+                    differences += 1;
+                }
+            }
+            (Some(a), None) => {
+                println!("Only in a: {}", procedure_name(a, a_st));
+                differences += 1;
+            }
+            (None, Some(b)) => {
+                println!("Only in b: {}", procedure_name(b, b_st));
+                differences += 1;
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::*;
+    use super::*;
+    use asmcode::*;
+    use register::*;
+
+    fn leaf(reg: register) -> Binasm {
+        Binasm::new(Endian::Big)
+            .ent(1)
+            .label(1)
+            .ins(zaddu, rrr(xr2, xr4, reg))
+            .ins(zjr, r(xr31))
+            .end(1)
+    }
+
+    #[test]
+    fn counts_differing_procedures() {
+        let a = program(Endian::Big);
+        assert_eq!(diff_binasm(a.bytes(), a.bytes(), None, None), 0);
+        assert_eq!(
+            diff_binasm(leaf(xr5).bytes(), leaf(xr6).bytes(), None, None),
+            1
+        );
+        // helper appears in only one of them
+        assert_eq!(diff_binasm(a.bytes(), leaf(xr5).bytes(), None, None), 2);
+    }
+
+    #[test]
+    fn pairs_procedures_by_order_without_symbol_tables() {
+        // The same two procedures under other symbol numbers, as after adding a symbol
+        let file = |first: i32, second: i32| {
+            Binasm::new(Endian::Big)
+                .ent(first)
+                .label(first)
+                .ins(zjal, a(second))
+                .end(first)
+                .ent(second)
+                .label(second)
+                .ins(zjr, r(xr31))
+                .end(second)
+        };
+        assert_eq!(
+            diff_binasm(file(1, 2).bytes(), file(5, 3).bytes(), None, None),
+            0
+        );
+    }
+
+    #[test]
+    fn pairs_procedures_by_name_with_symbol_tables() {
+        let st = SymbolTable::read(&crate::st::fixture::symbol_table(true, 0), 0).unwrap();
+        // helper (SYM_2) moved ahead of main (SYM_1)
+        let a = Binasm::new(Endian::Big)
+            .ent(1)
+            .label(1)
+            .ins(zjr, r(xr31))
+            .end(1)
+            .ent(2)
+            .label(2)
+            .ins(zaddu, rrr(xr2, xr4, xr5))
+            .end(2);
+        let b = Binasm::new(Endian::Big)
+            .ent(2)
+            .label(2)
+            .ins(zaddu, rrr(xr2, xr4, xr5))
+            .end(2)
+            .ent(1)
+            .label(1)
+            .ins(zjr, r(xr31))
+            .end(1);
+        assert_eq!(diff_binasm(a.bytes(), b.bytes(), Some(&st), Some(&st)), 0);
+        assert_eq!(diff_binasm(a.bytes(), b.bytes(), None, None), 2);
+    }
+
+    #[test]
+    fn ignores_the_byte_order() {
+        let a = program(Endian::Big);
+        let b = program(Endian::Little);
+        assert_eq!(diff_binasm(a.bytes(), b.bytes(), None, None), 0);
+    }
+}
@@ -0,0 +1,123 @@
+// Disassembly of the .text section of an ELF object into binasm's syntax, so that an object can be
+// read and compared in the same form as the binasm it was assembled from.
+
+use std::collections::BTreeMap;
+
+use super::encode::decode;
+use super::expand::{Insn, Reloc};
+use super::*;
+use crate::elf::{self, Elf};
+
+// Points a decoded branch at a local label
+fn with_label(args: &ArgFormat, label: i32) -> ArgFormat {
+    let mut args = args.clone();
+    if let ArgFormat::frrl { symno, .. } | ArgFormat::frl { symno, .. } | ArgFormat::fl { symno } =
+        &mut args
+    {
+        *symno = -label;
+    }
+    args
+}
+
+// Prints .text of an ELF object as binasm instructions, with a label for each function symbol and
+// for each branch target, and the relocation applied to each word as a comment. Relocated fields
+// are shown as stored in the object. Returns None if the file is not a big-endian ELF object with a
+// .text section, or else the number of words that did not decode, which are printed as .word.
+pub fn disassemble_elf(bytes: &[u8]) -> Option<usize> {
+    let elf = Elf::parse(bytes)?;
+    let text_index = elf.sections.iter().position(|s| s.name == ".text")?;
+    let text = &elf.sections[text_index];
+    let words: Vec<u32> = elf
+        .section_data(text)?
+        .chunks_exact(4)
+        // Elf::parse only accepts big-endian objects
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .collect();
+    let insns: Vec<Option<Insn>> = words.iter().map(|word| decode(*word)).collect();
+
+    let symbols = elf.symbols();
+    let symbol_name = |index: u32| match symbols.get(index as usize) {
+        Some(sym) if sym.st_type() == elf::STT_SECTION => elf
+            .sections
+            .get(sym.st_shndx as usize)
+            .map_or("", |s| s.name.as_str()),
+        Some(sym) => sym.name.as_str(),
+        None => "",
+    };
+    let functions: BTreeMap<u32, &str> = symbols
+        .iter()
+        .filter(|sym| sym.st_shndx as usize == text_index && sym.st_type() == elf::STT_FUNC)
+        .map(|sym| (sym.st_value, sym.name.as_str()))
+        .collect();
+    let mut relocs: BTreeMap<u32, Vec<&elf::Rel>> = BTreeMap::new();
+    let rels = elf.relocations(text);
+    for rel in &rels {
+        relocs.entry(rel.r_offset).or_default().push(rel);
+    }
+
+    // Local labels are numbered in address order
+    let target = |i: usize, insn: &Insn| match insn.reloc {
+        Reloc::Pc(pc) => u32::try_from(i as i64 * 4 + pc as i64)
+            .ok()
+            .filter(|addr| (*addr as usize) < words.len() * 4),
+        _ => None,
+    };
+    let mut labels: BTreeMap<u32, i32> = insns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, insn)| target(i, insn.as_ref()?))
+        .map(|addr| (addr, 0))
+        .collect();
+    for (n, label) in labels.values_mut().enumerate() {
+        *label = n as i32 + 1;
+    }
+
+    let mut undecoded = 0;
+    let mut delay_slot = false;
+    for (i, (word, insn)) in words.iter().zip(&insns).enumerate() {
+        let addr = i as u32 * 4;
+        if let Some(name) = functions.get(&addr) {
+            println!("{name}:");
+        }
+        if let Some(label) = labels.get(&addr) {
+            println!("{}", Asm::ilabel { symno: -label });
+        }
+
+        let mut line = match insn {
+            Some(insn) => {
+                let args = match target(i, insn).and_then(|addr| labels.get(&addr)) {
+                    Some(label) => with_label(&insn.args, *label),
+                    None => insn.args.clone(),
+                };
+                Asm::iocode { op: insn.op, args }.to_string()
+            }
+            None => {
+                undecoded += 1;
+                format!("\t.word\t0x{word:08X}")
+            }
+        };
+        if delay_slot {
+            line = format!("\t {}", line.trim_start_matches('\t'));
+        }
+        if let Some(rels) = relocs.get(&addr) {
+            let comments: Vec<String> = rels
+                .iter()
+                .map(|rel| {
+                    format!(
+                        "{} {}",
+                        elf::reloc_type_name(rel.r_type),
+                        symbol_name(rel.r_sym)
+                    )
+                })
+                .collect();
+            line = format!("{line}\t# {}", comments.join(", "));
+        }
+        println!("{line}");
+
+        delay_slot = insn
+            .as_ref()
+            .is_some_and(|insn| transfer(insn.op).is_some());
+    }
+
+    Some(undecoded)
+}
@@ -0,0 +1,1059 @@
+// MIPS I-IV machine code for asmcode instructions, and the inverse decoding of instruction words.
+//
+// Operands follow binasm's order for each format. Branch targets come from a Reloc::Pc displacement;
+// symbolic jump targets and %hi/%lo operands are encoded as zero, for a relocation to fill in.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use strum::IntoEnumIterator;
+
+use super::expand::{expand, Insn, Layout, Reloc};
+use super::opinfo::{op_info, Isa};
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EncodeError {
+    // Pseudo-instruction that must be expanded first
+    Macro(asmcode),
+    // Opcode slot with no instruction behind it
+    NoInstruction(asmcode),
+    // Placeholder opcode slot whose instruction is not known
+    UnknownSlot(asmcode),
+    // Instruction with no machine encoding: extended precision, or not supported here
+    NoEncoding(asmcode),
+    Operands(asmcode, format),
+    BranchRange(asmcode, i32),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::Macro(op) => write!(f, "{} is a macro", op.to_string().trim_end()),
+            EncodeError::NoInstruction(op) => {
+                let name: &'static str = (*op).into();
+                write!(f, "opcode {name} is not an instruction")
+            }
+            EncodeError::UnknownSlot(op) => {
+                write!(f, "opcode slot {} has no known instruction", *op as usize)
+            }
+            EncodeError::NoEncoding(op) => {
+                write!(f, "{} has no machine encoding", op.to_string().trim_end())
+            }
+            EncodeError::Operands(op, form) => write!(
+                f,
+                "{} cannot be encoded with {form:?} operands",
+                op.to_string().trim_end()
+            ),
+            EncodeError::BranchRange(op, pc) => write!(
+                f,
+                "{} target .+{pc} is out of range",
+                op.to_string().trim_end()
+            ),
+        }
+    }
+}
+
+const SPECIAL: u32 = 0;
+const REGIMM: u32 = 1;
+const COP1X: u32 = 19;
+
+const FMT_S: u32 = 16;
+const FMT_D: u32 = 17;
+const FMT_W: u32 = 20;
+const FMT_L: u32 = 21;
+
+// Instruction layouts, named after an instruction that uses each
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Enc {
+    // addu rd, rs, rt
+    Alu(u32),
+    // sll rd, rt, sa
+    Shift(u32),
+    // sllv rd, rt, rs
+    ShiftVariable(u32),
+    // mult rs, rt
+    MulDiv(u32),
+    // mfhi rd
+    MoveFromHiLo(u32),
+    // mthi rs
+    MoveToHiLo(u32),
+    Jr,
+    Jalr,
+    // teq rs, rt
+    Trap(u32),
+    // movn rd, rs, rt
+    CondMove(u32),
+    // movt rd, rs, cc
+    MoveOnFp(bool),
+    // addiu rt, rs, immediate
+    Immediate(u32),
+    Lui,
+    // lw rt, offset(base)
+    Memory(u32),
+    // beq rs, rt, offset
+    Branch2(u32),
+    // blez rs, offset
+    Branch1(u32),
+    // bltz rs, offset
+    RegImm(u32),
+    // j target
+    Jump(u32),
+    // bc1t offset: coprocessor, true/false, likely
+    CopBranch(u32, bool, bool),
+    // mfc1 rt, fs: coprocessor and rs field
+    CopMove(u32, u32),
+    // c1 cofun
+    CopOperation(u32),
+    // add.s fd, fs, ft: fmt, funct
+    Fp(u32, u32),
+    // mov.s fd, fs
+    FpUnary(u32, u32),
+    // c.eq.s fs, ft: fmt, condition
+    FpCompare(u32, u32),
+    // movt.s fd, fs, cc: fmt, true/false
+    FpMoveOnFp(u32, bool),
+    // movn.s fd, fs, rt
+    FpCondMove(u32, u32),
+    // madd.s fd, fr, fs, ft
+    FpMultiplyAdd(u32),
+    // lwxc1 fd, index(base); stores put fs where loads put fd
+    Indexed(u32, bool),
+    // Fixed word, plus break and syscall codes
+    Fixed(u32),
+    Break,
+    Syscall,
+}
+
+fn encoding(op: asmcode) -> Result<Enc, EncodeError> {
+    use asmcode::*;
+    use Enc::*;
+    Ok(match op {
+        zsll => Shift(0),
+        zsrl => Shift(2),
+        zsra => Shift(3),
+        zsllv => ShiftVariable(4),
+        zsrlv => ShiftVariable(6),
+        zsrav => ShiftVariable(7),
+        zjr => Jr,
+        zjalr => Jalr,
+        zmovz => CondMove(10),
+        zmovn => CondMove(11),
+        zmovt => MoveOnFp(true),
+        zmovf => MoveOnFp(false),
+        zsyscall => Syscall,
+        zbreak => Break,
+        zsync => Fixed(15),
+        zmfhi => MoveFromHiLo(16),
+        zmthi => MoveToHiLo(17),
+        zmflo => MoveFromHiLo(18),
+        zmtlo => MoveToHiLo(19),
+        zdsllv => ShiftVariable(20),
+        zdsrlv => ShiftVariable(22),
+        zdsrav => ShiftVariable(23),
+        zmult => MulDiv(24),
+        zmultu => MulDiv(25),
+        zdiv => MulDiv(26),
+        zdivu => MulDiv(27),
+        zdmult => MulDiv(28),
+        zdmultu => MulDiv(29),
+        zddiv => MulDiv(30),
+        zddivu => MulDiv(31),
+        zadd => Alu(32),
+        zaddu => Alu(33),
+        zsub => Alu(34),
+        zsubu => Alu(35),
+        zand => Alu(36),
+        zor => Alu(37),
+        zxor => Alu(38),
+        znor => Alu(39),
+        zslt => Alu(42),
+        zsltu => Alu(43),
+        zdadd => Alu(44),
+        zdaddu => Alu(45),
+        zdsub => Alu(46),
+        zdsubu => Alu(47),
+        ztge => Trap(48),
+        ztgeu => Trap(49),
+        ztlt => Trap(50),
+        ztltu => Trap(51),
+        zteq => Trap(52),
+        ztne => Trap(54),
+        zdsll => Shift(56),
+        zdsrl => Shift(58),
+        zdsra => Shift(59),
+        zdsll32 => Shift(60),
+        zdsrl32 => Shift(62),
+        zdsra32 => Shift(63),
+        znop => Fixed(0),
+        zssnop => Fixed(1 << 6),
+
+        zbltz => RegImm(0),
+        zbgez => RegImm(1),
+        zbltzl => RegImm(2),
+        zbgezl => RegImm(3),
+        zbltzal => RegImm(16),
+        zbgezal => RegImm(17),
+        zbltzall => RegImm(18),
+        zbgezall => RegImm(19),
+
+        zj => Jump(2),
+        zjal => Jump(3),
+        zbeq => Branch2(4),
+        zbne => Branch2(5),
+        zblez => Branch1(6),
+        zbgtz => Branch1(7),
+        zaddi => Immediate(8),
+        zaddiu => Immediate(9),
+        zslti => Immediate(10),
+        zsltiu => Immediate(11),
+        zandi => Immediate(12),
+        zori => Immediate(13),
+        zxori => Immediate(14),
+        zlui => Lui,
+        zbeql => Branch2(20),
+        zbnel => Branch2(21),
+        zblezl => Branch1(22),
+        zbgtzl => Branch1(23),
+        zdaddi => Immediate(24),
+        zdaddiu => Immediate(25),
+        zldl => Memory(26),
+        zldr => Memory(27),
+        zlb => Memory(32),
+        zlh => Memory(33),
+        zlwl => Memory(34),
+        zlw => Memory(35),
+        zlbu => Memory(36),
+        zlhu => Memory(37),
+        zlwr => Memory(38),
+        zlwu => Memory(39),
+        zsb => Memory(40),
+        zsh => Memory(41),
+        zswl => Memory(42),
+        zsw => Memory(43),
+        zsdl => Memory(44),
+        zsdr => Memory(45),
+        zswr => Memory(46),
+        zll => Memory(48),
+        zlwc1 => Memory(49),
+        zlwc2 => Memory(50),
+        zpref => Memory(51),
+        zlwc3 => Memory(51),
+        zlld => Memory(52),
+        zldc1 => Memory(53),
+        zldc2 => Memory(54),
+        zld => Memory(55),
+        zsc => Memory(56),
+        zswc1 => Memory(57),
+        zswc2 => Memory(58),
+        zswc3 => Memory(59),
+        zscd => Memory(60),
+        zsdc1 => Memory(61),
+        zsdc2 => Memory(62),
+        zsd => Memory(63),
+
+        zmfc0 => CopMove(0, 0),
+        zdmfc0 => CopMove(0, 1),
+        zcfc0 => CopMove(0, 2),
+        zmtc0 => CopMove(0, 4),
+        zdmtc0 => CopMove(0, 5),
+        zctc0 => CopMove(0, 6),
+        zmfc1 => CopMove(1, 0),
+        zdmfc1 => CopMove(1, 1),
+        zcfc1 => CopMove(1, 2),
+        zmtc1 => CopMove(1, 4),
+        zdmtc1 => CopMove(1, 5),
+        zctc1 => CopMove(1, 6),
+        zmfc2 => CopMove(2, 0),
+        zdmfc2 => CopMove(2, 1),
+        zcfc2 => CopMove(2, 2),
+        zmtc2 => CopMove(2, 4),
+        zdmtc2 => CopMove(2, 5),
+        zctc2 => CopMove(2, 6),
+        zmfc3 => CopMove(3, 0),
+        zcfc3 => CopMove(3, 2),
+        zmtc3 => CopMove(3, 4),
+        zctc3 => CopMove(3, 6),
+        zbc0f => CopBranch(0, false, false),
+        zbc0t => CopBranch(0, true, false),
+        zbc0fl => CopBranch(0, false, true),
+        zbc0tl => CopBranch(0, true, true),
+        zbc1f => CopBranch(1, false, false),
+        zbc1t => CopBranch(1, true, false),
+        zbc1fl => CopBranch(1, false, true),
+        zbc1tl => CopBranch(1, true, true),
+        zbc2f => CopBranch(2, false, false),
+        zbc2t => CopBranch(2, true, false),
+        zbc2fl => CopBranch(2, false, true),
+        zbc2tl => CopBranch(2, true, true),
+        zbc3f => CopBranch(3, false, false),
+        zbc3t => CopBranch(3, true, false),
+        zc0 => CopOperation(0),
+        zc1 => CopOperation(1),
+        zc2 => CopOperation(2),
+        zc3 => CopOperation(3),
+        ztlbr => Fixed(0x4200_0001),
+        ztlbwi => Fixed(0x4200_0002),
+        ztlbwr => Fixed(0x4200_0006),
+        ztlbp => Fixed(0x4200_0008),
+        zrfe => Fixed(0x4200_0010),
+        zeret => Fixed(0x4200_0018),
+
+        fadd_s => Fp(FMT_S, 0),
+        fadd_d => Fp(FMT_D, 0),
+        fsub_s => Fp(FMT_S, 1),
+        fsub_d => Fp(FMT_D, 1),
+        fmul_s => Fp(FMT_S, 2),
+        fmul_d => Fp(FMT_D, 2),
+        fdiv_s => Fp(FMT_S, 3),
+        fdiv_d => Fp(FMT_D, 3),
+        fsqrt_s => FpUnary(FMT_S, 4),
+        fsqrt_d => FpUnary(FMT_D, 4),
+        fabs_s => FpUnary(FMT_S, 5),
+        fabs_d => FpUnary(FMT_D, 5),
+        fmov_s => FpUnary(FMT_S, 6),
+        fmov_d => FpUnary(FMT_D, 6),
+        fneg_s => FpUnary(FMT_S, 7),
+        fneg_d => FpUnary(FMT_D, 7),
+        zround_l_s => FpUnary(FMT_S, 8),
+        zround_l_d => FpUnary(FMT_D, 8),
+        ztrunc_l_s => FpUnary(FMT_S, 9),
+        ztrunc_l_d => FpUnary(FMT_D, 9),
+        zceil_l_s => FpUnary(FMT_S, 10),
+        zceil_l_d => FpUnary(FMT_D, 10),
+        zfloor_l_s => FpUnary(FMT_S, 11),
+        zfloor_l_d => FpUnary(FMT_D, 11),
+        fmovt_s => FpMoveOnFp(FMT_S, true),
+        fmovt_d => FpMoveOnFp(FMT_D, true),
+        fmovf_s => FpMoveOnFp(FMT_S, false),
+        fmovf_d => FpMoveOnFp(FMT_D, false),
+        fmovz_s => FpCondMove(FMT_S, 18),
+        fmovz_d => FpCondMove(FMT_D, 18),
+        fmovn_s => FpCondMove(FMT_S, 19),
+        fmovn_d => FpCondMove(FMT_D, 19),
+        frecip_s => FpUnary(FMT_S, 21),
+        frecip_d => FpUnary(FMT_D, 21),
+        frsqrt_s => FpUnary(FMT_S, 22),
+        frsqrt_d => FpUnary(FMT_D, 22),
+        fcvt_s_d => FpUnary(FMT_D, 32),
+        fcvt_s_w => FpUnary(FMT_W, 32),
+        fcvt_s_l => FpUnary(FMT_L, 32),
+        fcvt_d_s => FpUnary(FMT_S, 33),
+        fcvt_d_w => FpUnary(FMT_W, 33),
+        fcvt_d_l => FpUnary(FMT_L, 33),
+        fcvt_w_s => FpUnary(FMT_S, 36),
+        fcvt_w_d => FpUnary(FMT_D, 36),
+        fcvt_l_s => FpUnary(FMT_S, 37),
+        fcvt_l_d => FpUnary(FMT_D, 37),
+
+        fmadd_s => FpMultiplyAdd(0x20),
+        fmadd_d => FpMultiplyAdd(0x21),
+        fmsub_s => FpMultiplyAdd(0x28),
+        fmsub_d => FpMultiplyAdd(0x29),
+        fnmadd_s => FpMultiplyAdd(0x30),
+        fnmadd_d => FpMultiplyAdd(0x31),
+        fnmsub_s => FpMultiplyAdd(0x38),
+        fnmsub_d => FpMultiplyAdd(0x39),
+        zlwxc1 => Indexed(0, false),
+        zldxc1 => Indexed(1, false),
+        zswxc1 => Indexed(8, true),
+        zsdxc1 => Indexed(9, true),
+
+        zlgota | zbad | ztlbr1 => return Err(EncodeError::NoInstruction(op)),
+        _ if op_info(op).unknown => return Err(EncodeError::UnknownSlot(op)),
+        _ if op_info(op).is_macro => return Err(EncodeError::Macro(op)),
+        _ => {
+            let name: &'static str = op.into();
+            // Floating-point compares, one funct per condition in the order of the enum
+            if let Some(cond) = name.strip_prefix("fc_") {
+                let (cond, fmt) = cond.rsplit_once('_').unwrap();
+                const CONDS: [&str; 16] = [
+                    "f", "un", "eq", "ueq", "olt", "ult", "ole", "ule", "sf", "ngle", "seq", "ngl",
+                    "lt", "nge", "le", "ngt",
+                ];
+                let fmt = match fmt {
+                    "s" => FMT_S,
+                    "d" => FMT_D,
+                    _ => return Err(EncodeError::NoEncoding(op)),
+                };
+                let cond = CONDS.iter().position(|c| *c == cond).unwrap() as u32;
+                return Ok(FpCompare(fmt, cond));
+            }
+            // round.w, trunc.w, ceil.w and floor.w; the unsigned forms are macros
+            for (prefix, funct) in [
+                ("zround_w_", 12),
+                ("ztrunc_w_", 13),
+                ("zceil_w_", 14),
+                ("zfloor_w_", 15),
+            ] {
+                match name.strip_prefix(prefix) {
+                    Some("s") => return Ok(FpUnary(FMT_S, funct)),
+                    Some("d") => return Ok(FpUnary(FMT_D, funct)),
+                    _ => {}
+                }
+            }
+            return Err(EncodeError::NoEncoding(op));
+        }
+    })
+}
+
+fn field(reg: register) -> u32 {
+    reg as u32 & 0x1F
+}
+
+fn gpr(idx: u32) -> register {
+    register::from_repr(idx as usize).unwrap()
+}
+
+fn fpr(idx: u32) -> register {
+    register::from_repr(register::xfr0 as usize + idx as usize).unwrap()
+}
+
+// Register file of the rt operand of a coprocessor move: $f registers for coprocessor 1, plain
+// numbers otherwise
+fn cop_register(cop: u32, idx: u32, rs: u32) -> register {
+    if cop == 1 && rs != 2 && rs != 6 {
+        fpr(idx)
+    } else {
+        gpr(idx)
+    }
+}
+
+fn branch_offset(op: asmcode, reloc: Reloc) -> Result<u32, EncodeError> {
+    match reloc {
+        // Targets are relative to the delay slot
+        Reloc::Pc(pc) if pc % 4 == 0 && i16::try_from((pc - 4) / 4).is_ok() => {
+            Ok(((pc - 4) / 4) as u32 & 0xFFFF)
+        }
+        Reloc::Pc(pc) => Err(EncodeError::BranchRange(op, pc)),
+        _ => Ok(0),
+    }
+}
+
+// Encodes one machine instruction
+pub fn encode(insn: &Insn) -> Result<u32, EncodeError> {
+    use ArgFormat::*;
+    use Enc::*;
+    let op = insn.op;
+    let enc = encoding(op)?;
+    let bad = || EncodeError::Operands(op, insn.args.format());
+    let imm16 = |immediate: i32| immediate as u32 & 0xFFFF;
+    // Relocated immediates are left for the linker
+    let relocated = |immediate: i32| match insn.reloc {
+        Reloc::Sym(_) => 0,
+        _ => imm16(immediate),
+    };
+    let r = |opcode: u32, rs: u32, rt: u32, rd: u32, sa: u32, funct: u32| {
+        opcode << 26 | rs << 21 | rt << 16 | rd << 11 | sa << 6 | funct
+    };
+    let i = |opcode: u32, rs: u32, rt: u32, immediate: u32| {
+        opcode << 26 | rs << 21 | rt << 16 | immediate
+    };
+
+    Ok(match (enc, &insn.args) {
+        (Alu(funct), frrr { reg1, reg2, reg3 }) => {
+            r(SPECIAL, field(*reg2), field(*reg3), field(*reg1), 0, funct)
+        }
+        (
+            Shift(funct),
+            frri {
+                reg1,
+                reg2,
+                immediate,
+                ..
+            },
+        ) => r(
+            SPECIAL,
+            0,
+            field(*reg2),
+            field(*reg1),
+            *immediate as u32 & 0x1F,
+            funct,
+        ),
+        (ShiftVariable(funct), frrr { reg1, reg2, reg3 }) => {
+            r(SPECIAL, field(*reg3), field(*reg2), field(*reg1), 0, funct)
+        }
+        (MulDiv(funct), frr { reg1, reg2 }) => r(SPECIAL, field(*reg1), field(*reg2), 0, 0, funct),
+        (MoveFromHiLo(funct), fr { reg }) => r(SPECIAL, 0, 0, field(*reg), 0, funct),
+        (MoveToHiLo(funct), fr { reg }) => r(SPECIAL, field(*reg), 0, 0, 0, funct),
+        (Jr, fr { reg }) => r(SPECIAL, field(*reg), 0, 0, 0, 8),
+        (Jalr, fr { reg }) => r(SPECIAL, field(*reg), 0, 31, 0, 9),
+        (Jalr, frr { reg1, reg2 }) => r(SPECIAL, field(*reg2), 0, field(*reg1), 0, 9),
+        (Trap(funct), frr { reg1, reg2 }) => r(SPECIAL, field(*reg1), field(*reg2), 0, 0, funct),
+        (CondMove(funct), frrr { reg1, reg2, reg3 }) => {
+            r(SPECIAL, field(*reg2), field(*reg3), field(*reg1), 0, funct)
+        }
+        (
+            MoveOnFp(tf),
+            frri {
+                reg1,
+                reg2,
+                immediate,
+                ..
+            },
+        ) => {
+            let cc = (*immediate as u32 & 7) << 2 | tf as u32;
+            r(SPECIAL, field(*reg2), cc, field(*reg1), 0, 1)
+        }
+        (MoveOnFp(tf), frr { reg1, reg2 }) => {
+            r(SPECIAL, field(*reg2), tf as u32, field(*reg1), 0, 1)
+        }
+        (
+            Immediate(opcode),
+            frri {
+                reg1,
+                reg2,
+                immediate,
+                ..
+            },
+        ) => i(opcode, field(*reg2), field(*reg1), relocated(*immediate)),
+        (Lui, fri { reg, immediate, .. }) => i(15, 0, field(*reg), relocated(*immediate)),
+        (
+            Memory(opcode),
+            frob {
+                reg, offset, base, ..
+            },
+        ) => i(opcode, field(*base), field(*reg), relocated(*offset as i32)),
+        (Branch2(opcode), frrl { reg1, reg2, .. }) => i(
+            opcode,
+            field(*reg1),
+            field(*reg2),
+            branch_offset(op, insn.reloc)?,
+        ),
+        (Branch1(opcode), frl { reg, .. }) => {
+            i(opcode, field(*reg), 0, branch_offset(op, insn.reloc)?)
+        }
+        (RegImm(code), frl { reg, .. }) => {
+            i(REGIMM, field(*reg), code, branch_offset(op, insn.reloc)?)
+        }
+        (
+            Jump(opcode),
+            fa {
+                symno, immediate, ..
+            },
+        ) => {
+            let target = if *symno == 0 {
+                (*immediate as u32 >> 2) & 0x03FF_FFFF
+            } else {
+                0
+            };
+            opcode << 26 | target
+        }
+        (Jump(opcode), fl { .. }) => opcode << 26,
+        (CopBranch(cop, tf, likely), fl { .. }) => {
+            let rt = (likely as u32) << 1 | tf as u32;
+            i(16 + cop, 8, rt, branch_offset(op, insn.reloc)?)
+        }
+        (CopMove(cop, rs), frr { reg1, reg2 }) => r(16 + cop, rs, field(*reg1), field(*reg2), 0, 0),
+        (CopOperation(cop), fi { immediate }) => {
+            (16 + cop) << 26 | 1 << 25 | (*immediate as u32 & 0x01FF_FFFF)
+        }
+        (Fp(fmt, funct), frrr { reg1, reg2, reg3 }) => {
+            r(17, fmt, field(*reg3), field(*reg2), field(*reg1), funct)
+        }
+        (FpUnary(fmt, funct), frr { reg1, reg2 }) => {
+            r(17, fmt, 0, field(*reg2), field(*reg1), funct)
+        }
+        (FpCompare(fmt, cond), frr { reg1, reg2 }) => {
+            r(17, fmt, field(*reg2), field(*reg1), 0, 48 + cond)
+        }
+        (FpMoveOnFp(fmt, tf), frr { reg1, reg2 }) => {
+            r(17, fmt, tf as u32, field(*reg2), field(*reg1), 17)
+        }
+        (
+            FpMoveOnFp(fmt, tf),
+            frri {
+                reg1,
+                reg2,
+                immediate,
+                ..
+            },
+        ) => {
+            let cc = (*immediate as u32 & 7) << 2 | tf as u32;
+            r(17, fmt, cc, field(*reg2), field(*reg1), 17)
+        }
+        (FpCondMove(fmt, funct), frrr { reg1, reg2, reg3 }) => {
+            r(17, fmt, field(*reg3), field(*reg2), field(*reg1), funct)
+        }
+        (
+            FpMultiplyAdd(op4),
+            frrrr {
+                reg1,
+                reg2,
+                reg3,
+                reg4,
+            },
+        ) => r(
+            COP1X,
+            field(*reg2),
+            field(*reg4),
+            field(*reg3),
+            field(*reg1),
+            op4,
+        ),
+        (Indexed(funct, false), frrr { reg1, reg2, reg3 }) => {
+            r(COP1X, field(*reg3), field(*reg2), 0, field(*reg1), funct)
+        }
+        (Indexed(funct, true), frrr { reg1, reg2, reg3 }) => {
+            r(COP1X, field(*reg3), field(*reg2), field(*reg1), 0, funct)
+        }
+        (Fixed(word), _) => word,
+        (Break, fi { immediate }) => (*immediate as u32 & 0x3FF) << 16 | 13,
+        (Break | Syscall, _) => {
+            if matches!(enc, Break) {
+                13
+            } else {
+                12
+            }
+        }
+        _ => return Err(bad()),
+    })
+}
+
+fn decode_with(op: asmcode, enc: Enc, word: u32) -> Option<Insn> {
+    use ArgFormat::*;
+    use Enc::*;
+    let opcode = word >> 26;
+    let rs = word >> 21 & 0x1F;
+    let rt = word >> 16 & 0x1F;
+    let rd = word >> 11 & 0x1F;
+    let sa = word >> 6 & 0x1F;
+    let funct = word & 0x3F;
+    let simm = word as u16 as i16 as i32;
+    let pc = Reloc::Pc(simm * 4 + 4);
+    let cop = opcode.wrapping_sub(16);
+    let insn = |args| {
+        Some(Insn {
+            op,
+            args,
+            reloc: Reloc::None,
+        })
+    };
+    let branch = |args| {
+        Some(Insn {
+            op,
+            args,
+            reloc: pc,
+        })
+    };
+    let frri3 = |reg1, reg2, immediate| frri {
+        reg1,
+        reg2,
+        immediate,
+        mem_tag: 0,
+    };
+
+    match enc {
+        Alu(f) if opcode == SPECIAL && funct == f && sa == 0 => insn(frrr {
+            reg1: gpr(rd),
+            reg2: gpr(rs),
+            reg3: gpr(rt),
+        }),
+        Shift(f) if opcode == SPECIAL && funct == f && rs == 0 => {
+            insn(frri3(gpr(rd), gpr(rt), sa as i32))
+        }
+        ShiftVariable(f) if opcode == SPECIAL && funct == f && sa == 0 => insn(frrr {
+            reg1: gpr(rd),
+            reg2: gpr(rt),
+            reg3: gpr(rs),
+        }),
+        MulDiv(f) if opcode == SPECIAL && funct == f && rd == 0 && sa == 0 => insn(frr {
+            reg1: gpr(rs),
+            reg2: gpr(rt),
+        }),
+        MoveFromHiLo(f) if opcode == SPECIAL && funct == f && rs == 0 && rt == 0 && sa == 0 => {
+            insn(fr { reg: gpr(rd) })
+        }
+        MoveToHiLo(f) if opcode == SPECIAL && funct == f && rt == 0 && rd == 0 && sa == 0 => {
+            insn(fr { reg: gpr(rs) })
+        }
+        Jr if opcode == SPECIAL && funct == 8 && rt == 0 && rd == 0 => insn(fr { reg: gpr(rs) }),
+        Jalr if opcode == SPECIAL && funct == 9 && rt == 0 && rd == 31 => insn(fr { reg: gpr(rs) }),
+        Jalr if opcode == SPECIAL && funct == 9 && rt == 0 => insn(frr {
+            reg1: gpr(rd),
+            reg2: gpr(rs),
+        }),
+        Trap(f) if opcode == SPECIAL && funct == f => insn(frr {
+            reg1: gpr(rs),
+            reg2: gpr(rt),
+        }),
+        CondMove(f) if opcode == SPECIAL && funct == f && sa == 0 => insn(frrr {
+            reg1: gpr(rd),
+            reg2: gpr(rs),
+            reg3: gpr(rt),
+        }),
+        MoveOnFp(tf) if opcode == SPECIAL && funct == 1 && rt & 3 == tf as u32 => {
+            insn(frri3(gpr(rd), gpr(rs), (rt >> 2) as i32))
+        }
+        Immediate(o) if opcode == o => {
+            // Logical immediates are zero-extended
+            let immediate = if matches!(o, 12..=14) {
+                word as i32 & 0xFFFF
+            } else {
+                simm
+            };
+            insn(frri3(gpr(rt), gpr(rs), immediate))
+        }
+        Lui if opcode == 15 && rs == 0 => insn(fri {
+            reg: gpr(rt),
+            immediate: word as i32 & 0xFFFF,
+            mem_tag: 0,
+        }),
+        Memory(o) if opcode == o => {
+            let reg = match op {
+                asmcode::zlwc1 | asmcode::zldc1 | asmcode::zswc1 | asmcode::zsdc1 => fpr(rt),
+                _ => gpr(rt),
+            };
+            insn(frob {
+                symno: 0,
+                reg,
+                offset: simm as i16,
+                base: gpr(rs),
+                mem_tag: 0,
+            })
+        }
+        Branch2(o) if opcode == o => branch(frrl {
+            symno: 0,
+            reg1: gpr(rs),
+            reg2: gpr(rt),
+        }),
+        Branch1(o) if opcode == o && rt == 0 => branch(frl {
+            symno: 0,
+            reg: gpr(rs),
+        }),
+        RegImm(code) if opcode == REGIMM && rt == code => branch(frl {
+            symno: 0,
+            reg: gpr(rs),
+        }),
+        Jump(o) if opcode == o => insn(fa {
+            symno: 0,
+            base: register::xnoreg,
+            mem_tag: 0,
+            immediate: ((word & 0x03FF_FFFF) << 2) as i32,
+        }),
+        CopBranch(c, tf, likely)
+            if cop == c && rs == 8 && rt == (likely as u32) << 1 | tf as u32 =>
+        {
+            branch(fl { symno: 0 })
+        }
+        CopMove(c, code) if cop == c && rs == code && sa == 0 && funct == 0 => insn(frr {
+            reg1: gpr(rt),
+            reg2: cop_register(c, rd, code),
+        }),
+        CopOperation(c) if cop == c && word & 1 << 25 != 0 => insn(fi {
+            immediate: (word & 0x01FF_FFFF) as i32,
+        }),
+        Fp(fmt, f) if opcode == 17 && rs == fmt && funct == f => insn(frrr {
+            reg1: fpr(sa),
+            reg2: fpr(rd),
+            reg3: fpr(rt),
+        }),
+        FpUnary(fmt, f) if opcode == 17 && rs == fmt && funct == f && rt == 0 => insn(frr {
+            reg1: fpr(sa),
+            reg2: fpr(rd),
+        }),
+        FpCompare(fmt, cond) if opcode == 17 && rs == fmt && funct == 48 + cond && sa == 0 => {
+            insn(frr {
+                reg1: fpr(rd),
+                reg2: fpr(rt),
+            })
+        }
+        FpMoveOnFp(fmt, tf) if opcode == 17 && rs == fmt && funct == 17 && rt & 3 == tf as u32 => {
+            if rt >> 2 == 0 {
+                insn(frr {
+                    reg1: fpr(sa),
+                    reg2: fpr(rd),
+                })
+            } else {
+                insn(frri3(fpr(sa), fpr(rd), (rt >> 2) as i32))
+            }
+        }
+        FpCondMove(fmt, f) if opcode == 17 && rs == fmt && funct == f => insn(frrr {
+            reg1: fpr(sa),
+            reg2: fpr(rd),
+            reg3: gpr(rt),
+        }),
+        FpMultiplyAdd(op4) if opcode == COP1X && funct == op4 => insn(frrrr {
+            reg1: fpr(sa),
+            reg2: fpr(rs),
+            reg3: fpr(rd),
+            reg4: fpr(rt),
+        }),
+        Indexed(f, false) if opcode == COP1X && funct == f && rd == 0 => insn(frrr {
+            reg1: fpr(sa),
+            reg2: gpr(rt),
+            reg3: gpr(rs),
+        }),
+        Indexed(f, true) if opcode == COP1X && funct == f && sa == 0 => insn(frrr {
+            reg1: fpr(rd),
+            reg2: gpr(rt),
+            reg3: gpr(rs),
+        }),
+        Fixed(w) if word == w => insn(forrr),
+        Break if opcode == SPECIAL && funct == 13 => insn(fi {
+            immediate: (word >> 16 & 0x3FF) as i32,
+        }),
+        Syscall if opcode == SPECIAL && funct == 12 => insn(forrr),
+        _ => None,
+    }
+}
+
+// Decodes one instruction word. Where two opcodes share an encoding the first in asmcode order wins,
+// so lwc3 decodes as pref and ssnop as sll, and a zero word decodes as nop. Words with bits set that no operand
+// accounts for, such as trap codes, do not decode.
+pub fn decode(word: u32) -> Option<Insn> {
+    if word == 0 {
+        return decode_with(asmcode::znop, Enc::Fixed(0), word);
+    }
+    static ENCODINGS: OnceLock<Vec<(asmcode, Enc)>> = OnceLock::new();
+    let encodings = ENCODINGS.get_or_init(|| {
+        let mut encodings: Vec<(asmcode, Enc)> = asmcode::iter()
+            .filter_map(|op| Some((op, encoding(op).ok()?)))
+            .collect();
+        // c0-c3 cover every coprocessor operation, so they are tried after the specific ones
+        encodings.sort_by_key(|(_, enc)| matches!(enc, Enc::CopOperation(_)));
+        encodings
+    });
+    encodings
+        .iter()
+        .filter_map(|(op, enc)| decode_with(*op, *enc, word))
+        .find(|insn| encode(insn) == Ok(word))
+}
+
+// Prints the machine code for every instruction in a binasm file after macro expansion, flagging
+// words that do not decode back to the instruction. Returns the number of instructions that could
+// not be encoded or did not round-trip.
+pub fn print_encoded(bytes: &[u8], isa: Isa) -> usize {
+    let mut errors = 0;
+    let records = read_records(bytes);
+    let layout = Layout::collect(&records, Endian::detect(bytes));
+    for record in records {
+        let Asm::iocode { op, args } = &record.asm else {
+            continue;
+        };
+        for insn in expand(*op, args, &record.set, isa, &layout).insns {
+            match encode(&insn) {
+                Ok(word) => {
+                    // Relocated fields are zero in the word, so only check what is fully encoded
+                    let relocated = match (insn.reloc, &insn.args) {
+                        (Reloc::Sym(_), _) => true,
+                        (Reloc::None, ArgFormat::fa { symno, .. }) => *symno != 0,
+                        (
+                            Reloc::None,
+                            ArgFormat::fl { .. } | ArgFormat::frl { .. } | ArgFormat::frrl { .. },
+                        ) => true,
+                        _ => false,
+                    };
+                    let round_trip = relocated
+                        || decode(word).is_some_and(|d| {
+                            d.to_string() == insn.to_string() && d.reloc == insn.reloc
+                        });
+                    println!("0x{word:08X}{insn}");
+                    if !round_trip {
+                        println!("\t# 0x{:06X}: decodes differently", record.offset);
+                        errors += 1;
+                    }
+                }
+                Err(err) => {
+                    println!("          {insn}");
+                    println!("\t# 0x{:06X}: {err}", record.offset);
+                    errors += 1;
+                }
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An instruction of each layout, with operands that land in distinct fields
+    fn sample(op: asmcode, enc: Enc) -> Insn {
+        use ArgFormat::*;
+        use Enc::*;
+        let (args, reloc) = match enc {
+            Alu(_) | ShiftVariable(_) | CondMove(_) => (
+                frrr {
+                    reg1: gpr(2),
+                    reg2: gpr(3),
+                    reg3: gpr(4),
+                },
+                Reloc::None,
+            ),
+            Shift(_) | Immediate(_) | MoveOnFp(_) => (
+                frri {
+                    reg1: gpr(2),
+                    reg2: gpr(3),
+                    immediate: if matches!(enc, Immediate(_)) {
+                        0x1234
+                    } else {
+                        3
+                    },
+                    mem_tag: 0,
+                },
+                Reloc::None,
+            ),
+            MulDiv(_) | Trap(_) => (
+                frr {
+                    reg1: gpr(3),
+                    reg2: gpr(4),
+                },
+                Reloc::None,
+            ),
+            MoveFromHiLo(_) | MoveToHiLo(_) | Jr | Jalr => (fr { reg: gpr(5) }, Reloc::None),
+            Lui => (
+                fri {
+                    reg: gpr(2),
+                    immediate: 0x1234,
+                    mem_tag: 0,
+                },
+                Reloc::None,
+            ),
+            Memory(_) => (
+                frob {
+                    symno: 0,
+                    reg: match op {
+                        asmcode::zlwc1 | asmcode::zldc1 | asmcode::zswc1 | asmcode::zsdc1 => fpr(2),
+                        _ => gpr(2),
+                    },
+                    offset: -8,
+                    base: gpr(29),
+                    mem_tag: 0,
+                },
+                Reloc::None,
+            ),
+            Branch2(_) => (
+                frrl {
+                    symno: 0,
+                    reg1: gpr(2),
+                    reg2: gpr(3),
+                },
+                Reloc::Pc(0x40),
+            ),
+            Branch1(_) | RegImm(_) => (
+                frl {
+                    symno: 0,
+                    reg: gpr(2),
+                },
+                Reloc::Pc(-0x20),
+            ),
+            Jump(_) => (
+                fa {
+                    symno: 0,
+                    base: register::xnoreg,
+                    mem_tag: 0,
+                    immediate: 0x1000,
+                },
+                Reloc::None,
+            ),
+            CopBranch(..) => (fl { symno: 0 }, Reloc::Pc(8)),
+            CopMove(cop, rs) => (
+                frr {
+                    reg1: gpr(2),
+                    reg2: cop_register(cop, 5, rs),
+                },
+                Reloc::None,
+            ),
+            CopOperation(_) => (fi { immediate: 0x12345 }, Reloc::None),
+            Fp(..) => (
+                frrr {
+                    reg1: fpr(2),
+                    reg2: fpr(4),
+                    reg3: fpr(6),
+                },
+                Reloc::None,
+            ),
+            FpUnary(..) | FpCompare(..) => (
+                frr {
+                    reg1: fpr(4),
+                    reg2: fpr(6),
+                },
+                Reloc::None,
+            ),
+            FpMoveOnFp(..) => (
+                frri {
+                    reg1: fpr(2),
+                    reg2: fpr(4),
+                    immediate: 3,
+                    mem_tag: 0,
+                },
+                Reloc::None,
+            ),
+            FpCondMove(..) => (
+                frrr {
+                    reg1: fpr(2),
+                    reg2: fpr(4),
+                    reg3: gpr(5),
+                },
+                Reloc::None,
+            ),
+            FpMultiplyAdd(_) => (
+                frrrr {
+                    reg1: fpr(2),
+                    reg2: fpr(4),
+                    reg3: fpr(6),
+                    reg4: fpr(8),
+                },
+                Reloc::None,
+            ),
+            Indexed(..) => (
+                frrr {
+                    reg1: fpr(2),
+                    reg2: gpr(5),
+                    reg3: gpr(6),
+                },
+                Reloc::None,
+            ),
+            Break => (fi { immediate: 7 }, Reloc::None),
+            Fixed(_) | Syscall => (forrr, Reloc::None),
+        };
+        Insn { op, args, reloc }
+    }
+
+    fn same(a: &Insn, b: &Insn) -> bool {
+        a.op == b.op && format!("{:?}", a.args) == format!("{:?}", b.args) && a.reloc == b.reloc
+    }
+
+    #[test]
+    fn decode_inverts_encode() {
+        for op in asmcode::iter() {
+            let Ok(enc) = encoding(op) else {
+                continue;
+            };
+            let name: &str = op.into();
+            let insn = sample(op, enc);
+            let word = encode(&insn).unwrap_or_else(|err| panic!("{name}: {err}"));
+            let back = decode_with(op, enc, word);
+            assert!(
+                back.as_ref().is_some_and(|back| same(back, &insn)),
+                "{name} {:?} encodes as 0x{word:08X}, which decodes as {:?}",
+                insn.args,
+                back.map(|back| back.args)
+            );
+
+            // Opcodes sharing an encoding decode as the first of them, which encodes the same
+            let first =
+                decode(word).unwrap_or_else(|| panic!("{name}: 0x{word:08X} does not decode"));
+            assert_eq!(encode(&first), Ok(word), "{name} decodes as {first:?}");
+        }
+    }
+
+    #[test]
+    fn round_trips_each_family() {
+        use asmcode::*;
+        for op in [
+            fadd_s, fc_eq_s, fmadd_s, zlwxc1, zbeql, zbc1tl, zbc0tl, zmfc1, zctc1, zdmfc1,
+        ] {
+            let name: &str = op.into();
+            let enc = encoding(op).unwrap_or_else(|err| panic!("{name}: {err}"));
+            let insn = sample(op, enc);
+            let word = encode(&insn).unwrap_or_else(|err| panic!("{name}: {err}"));
+            assert!(
+                decode_with(op, enc, word).is_some_and(|back| same(&back, &insn)),
+                "{name} does not round-trip through 0x{word:08X}"
+            );
+        }
+    }
+}
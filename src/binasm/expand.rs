@@ -0,0 +1,1489 @@
+// Expansion of as1's macro instructions into the machine instructions it assembles them to. Symbols
+// in the small data area are addressed relative to $gp, others with a %hi/%lo pair. Which symbols
+// are small is worked out from the file itself, as as1 does under its default -G 8: labels in
+// .sdata and .comm/.lcomm of at most 8 bytes. A symbol the file neither defines nor declares common
+// may have been given a small size by .extern, which is not decoded, so it is addressed with
+// %hi/%lo and a warning.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::opinfo::{defs_uses, op_info, Isa, Shape};
+use super::*;
+
+// Relocation or local branch target attached to an expanded instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reloc {
+    None,
+    // Symbolic immediate: the %hi half of an address, adjusted for the sign of the low half, or
+    // the %lo half
+    Sym(SymOperand),
+    // Branch target within the expansion, in bytes from the branch
+    Pc(i32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Insn {
+    pub op: asmcode,
+    pub args: ArgFormat,
+    pub reloc: Reloc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Warning {
+    // The expansion needs $at under .set noat
+    UsesAt,
+    // The instruction expands to more than one instruction under .set nomacro
+    Macro,
+    // The macro is not expanded yet and was passed through unchanged
+    NotExpanded,
+    // A symbol whose placement the file does not give was addressed with %hi/%lo
+    ExternalAddress,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UsesAt => write!(f, "macro instruction used $at"),
+            Warning::Macro => write!(f, "macro instruction expanded into multiple instructions"),
+            Warning::NotExpanded => write!(f, "macro instruction not expanded"),
+            Warning::ExternalAddress => {
+                write!(
+                    f,
+                    "external symbol addressed with %hi/%lo; as1 uses $gp if it is small"
+                )
+            }
+        }
+    }
+}
+
+// as1's default -G: data objects of at most this many bytes go in the small data area
+const GP_SIZE: u32 = 8;
+
+// Where the symbols of a file live, as far as the file says, and the byte order of the target
+pub struct Layout {
+    endian: Endian,
+    // Symbols in the small data area, addressed relative to $gp
+    small: HashSet<i32>,
+    // Symbols the file defines or declares common
+    placed: HashSet<i32>,
+    // Symbols declared .globl or .comm, visible outside the file
+    global: HashSet<i32>,
+}
+
+impl Layout {
+    pub fn collect(records: &[Record], endian: Endian) -> Layout {
+        let mut layout = Layout {
+            endian,
+            small: HashSet::new(),
+            placed: HashSet::new(),
+            global: HashSet::new(),
+        };
+        let mut in_sdata = false;
+        for record in records {
+            match record.asm {
+                Asm::isdata { .. } => in_sdata = true,
+                Asm::idata { .. } | Asm::irdata { .. } | Asm::itext => in_sdata = false,
+                Asm::ilabel { symno } | Asm::ilab { symno } | Asm::ient { symno, .. } => {
+                    layout.placed.insert(symno);
+                    if in_sdata {
+                        layout.small.insert(symno);
+                    }
+                }
+                Asm::iglobal { symno } => {
+                    layout.global.insert(symno);
+                }
+                Asm::icomm { symno, length } | Asm::ilcomm { symno, length } => {
+                    layout.placed.insert(symno);
+                    if matches!(record.asm, Asm::icomm { .. }) {
+                        layout.global.insert(symno);
+                    }
+                    if length <= GP_SIZE {
+                        layout.small.insert(symno);
+                    }
+                }
+                _ => {}
+            }
+        }
+        layout
+    }
+
+    fn is_small(&self, symno: i32) -> bool {
+        self.small.contains(&symno)
+    }
+
+    // Whether a symbol is defined in this file and not visible outside it. Local labels always are.
+    fn is_local(&self, sym: SymRef) -> bool {
+        match sym {
+            SymRef::None => false,
+            SymRef::Label(_) => true,
+            SymRef::Symbol(n) => {
+                let symno = n as i32;
+                self.placed.contains(&symno) && !self.global.contains(&symno)
+            }
+        }
+    }
+}
+
+pub struct Expansion {
+    pub insns: Vec<Insn>,
+    pub warnings: Vec<Warning>,
+}
+
+const ZERO: register = register::xr0;
+const AT: register = register::xr1;
+const T9: register = register::xr25;
+const GP: register = register::xr28;
+const SP: register = register::xr29;
+
+fn insn(op: asmcode, args: ArgFormat) -> Insn {
+    Insn {
+        op,
+        args,
+        reloc: Reloc::None,
+    }
+}
+
+fn rrr(op: asmcode, reg1: register, reg2: register, reg3: register) -> Insn {
+    insn(op, ArgFormat::frrr { reg1, reg2, reg3 })
+}
+
+fn rri(op: asmcode, reg1: register, reg2: register, immediate: i32) -> Insn {
+    insn(
+        op,
+        ArgFormat::frri {
+            reg1,
+            reg2,
+            immediate,
+            mem_tag: 0,
+        },
+    )
+}
+
+fn rr(op: asmcode, reg1: register, reg2: register) -> Insn {
+    insn(op, ArgFormat::frr { reg1, reg2 })
+}
+
+fn r(op: asmcode, reg: register) -> Insn {
+    insn(op, ArgFormat::fr { reg })
+}
+
+fn ob(op: asmcode, reg: register, offset: i16, base: register) -> Insn {
+    insn(
+        op,
+        ArgFormat::frob {
+            symno: 0,
+            reg,
+            offset,
+            base,
+            mem_tag: 0,
+        },
+    )
+}
+
+fn nop() -> Insn {
+    insn(asmcode::znop, ArgFormat::forrr)
+}
+
+fn brk(code: i32) -> Insn {
+    insn(asmcode::zbreak, ArgFormat::fi { immediate: code })
+}
+
+// Two-register branch to a label, or to `pc` bytes ahead when `symno` is 0
+fn branch2(op: asmcode, reg1: register, reg2: register, symno: i32, pc: i32) -> Insn {
+    Insn {
+        op,
+        args: ArgFormat::frrl { symno, reg1, reg2 },
+        reloc: if symno == 0 {
+            Reloc::Pc(pc)
+        } else {
+            Reloc::None
+        },
+    }
+}
+
+fn branch1(op: asmcode, reg: register, symno: i32, pc: i32) -> Insn {
+    Insn {
+        op,
+        args: ArgFormat::frl { symno, reg },
+        reloc: if symno == 0 {
+            Reloc::Pc(pc)
+        } else {
+            Reloc::None
+        },
+    }
+}
+
+fn lui_hi(reg: register, symno: i32, addend: i32) -> Insn {
+    Insn {
+        op: asmcode::zlui,
+        args: ArgFormat::fri {
+            reg,
+            immediate: 0,
+            mem_tag: 0,
+        },
+        reloc: Reloc::Sym(SymOperand {
+            sym: SymRef::from_symno(symno),
+            addend,
+            reloc: RelocOp::Hi,
+        }),
+    }
+}
+
+fn with_lo(mut insn: Insn, symno: i32, addend: i32) -> Insn {
+    insn.reloc = Reloc::Sym(SymOperand {
+        sym: SymRef::from_symno(symno),
+        addend,
+        reloc: RelocOp::Lo,
+    });
+    insn
+}
+
+// Sets the offset of a load or store to the low half of `offset`, for a base holding the high half
+fn with_offset(mut insn: Insn, offset: i32) -> Insn {
+    if let ArgFormat::frob { offset: o, .. } = &mut insn.args {
+        *o = offset as i16;
+    }
+    insn
+}
+
+fn with_gp_rel(mut insn: Insn, symno: i32, addend: i32) -> Insn {
+    insn.reloc = Reloc::Sym(SymOperand {
+        sym: SymRef::from_symno(symno),
+        addend,
+        reloc: RelocOp::GpRel,
+    });
+    insn
+}
+
+fn fits_i16(value: i32) -> bool {
+    i16::try_from(value).is_ok()
+}
+
+fn fits_u16(value: i32) -> bool {
+    (0..=0xFFFF).contains(&value)
+}
+
+// High half of a constant for lui, rounded so that adding the sign-extended low half gives it back
+fn hi_adjusted(value: i32) -> i32 {
+    ((value as u32).wrapping_add(0x8000) >> 16) as i32
+}
+
+// li: the shortest sequence that loads a 32-bit constant. Constants that fit either form are
+// loaded with addiu.
+fn load_immediate(reg: register, value: i32) -> Vec<Insn> {
+    if fits_i16(value) {
+        vec![rri(asmcode::zaddiu, reg, ZERO, value)]
+    } else if fits_u16(value) {
+        vec![rri(asmcode::zori, reg, ZERO, value)]
+    } else if value & 0xFFFF == 0 {
+        vec![insn(
+            asmcode::zlui,
+            ArgFormat::fri {
+                reg,
+                immediate: (value as u32 >> 16) as i32,
+                mem_tag: 0,
+            },
+        )]
+    } else {
+        vec![
+            insn(
+                asmcode::zlui,
+                ArgFormat::fri {
+                    reg,
+                    immediate: (value as u32 >> 16) as i32,
+                    mem_tag: 0,
+                },
+            ),
+            rri(asmcode::zori, reg, reg, value & 0xFFFF),
+        ]
+    }
+}
+
+// Instructions that put an address in `reg`, for la and for the unaligned accesses
+fn load_address(
+    reg: register,
+    symno: i32,
+    offset: i32,
+    base: register,
+    layout: &Layout,
+) -> Vec<Insn> {
+    let mut insns = if symno == 0 {
+        if base != ZERO && base != register::xnoreg && fits_i16(offset) {
+            return vec![rri(asmcode::zaddiu, reg, base, offset)];
+        }
+        load_immediate(reg, offset)
+    } else if layout.is_small(symno) {
+        vec![with_gp_rel(rri(asmcode::zaddiu, reg, GP, 0), symno, offset)]
+    } else {
+        vec![
+            lui_hi(reg, symno, offset),
+            with_lo(rri(asmcode::zaddiu, reg, reg, 0), symno, offset),
+        ]
+    };
+    if base != ZERO && base != register::xnoreg {
+        insns.push(rrr(asmcode::zaddu, reg, reg, base));
+    }
+    insns
+}
+
+// A load or store whose address does not fit a 16-bit offset from a register. Loads build the
+// address in their destination register, stores in $at.
+fn memory_access(
+    op: asmcode,
+    reg: register,
+    symno: i32,
+    offset: i32,
+    base: register,
+    layout: &Layout,
+) -> Vec<Insn> {
+    let is_gp_load = reg != ZERO
+        && (reg as usize) < register::xfr0 as usize
+        && matches!(op_info(op).shape, Shape::Load);
+    let tmp = if is_gp_load { reg } else { AT };
+    memory_accesses(&[(op, reg, 0)], tmp, symno, offset, base, layout)
+}
+
+// Loads or stores at `displacement` bytes past one address, sharing the instructions that build it
+// in `tmp`
+fn memory_accesses(
+    accesses: &[(asmcode, register, i32)],
+    tmp: register,
+    symno: i32,
+    offset: i32,
+    base: register,
+    layout: &Layout,
+) -> Vec<Insn> {
+    let base = if base == register::xnoreg { ZERO } else { base };
+    let access = |insns: &mut Vec<Insn>, base: register, lo: &dyn Fn(Insn, i32) -> Insn| {
+        for (op, reg, displacement) in accesses {
+            insns.push(lo(
+                ob(*op, *reg, 0, base),
+                offset.wrapping_add(*displacement),
+            ));
+        }
+    };
+
+    let mut insns = Vec::new();
+    if symno == 0 {
+        let fits = accesses
+            .iter()
+            .all(|(_, _, d)| fits_i16(offset.wrapping_add(*d)));
+        if fits {
+            access(&mut insns, base, &with_offset);
+            return insns;
+        }
+        let hi = hi_adjusted(offset);
+        if accesses
+            .iter()
+            .any(|(_, _, d)| hi_adjusted(offset.wrapping_add(*d)) != hi)
+        {
+            // The accesses straddle a 64K boundary and need an address each
+            return accesses
+                .iter()
+                .flat_map(|(op, reg, d)| {
+                    memory_accesses(
+                        &[(*op, *reg, 0)],
+                        tmp,
+                        0,
+                        offset.wrapping_add(*d),
+                        base,
+                        layout,
+                    )
+                })
+                .collect();
+        }
+        insns.push(insn(
+            asmcode::zlui,
+            ArgFormat::fri {
+                reg: tmp,
+                immediate: hi,
+                mem_tag: 0,
+            },
+        ));
+        if base != ZERO {
+            insns.push(rrr(asmcode::zaddu, tmp, tmp, base));
+        }
+        access(&mut insns, tmp, &with_offset);
+    } else if layout.is_small(symno) {
+        let gp_base = if base == ZERO {
+            GP
+        } else {
+            insns.push(rrr(asmcode::zaddu, tmp, GP, base));
+            tmp
+        };
+        access(&mut insns, gp_base, &|insn, offset| {
+            with_gp_rel(insn, symno, offset)
+        });
+    } else {
+        insns.push(lui_hi(tmp, symno, offset));
+        if base != ZERO {
+            insns.push(rrr(asmcode::zaddu, tmp, tmp, base));
+        }
+        access(&mut insns, tmp, &|insn, offset| {
+            with_lo(insn, symno, offset)
+        });
+    }
+    insns
+}
+
+// Immediate form of a three-register ALU operation, and whether the immediate is zero-extended
+fn immediate_form(op: asmcode) -> Option<(asmcode, bool)> {
+    Some(match op {
+        asmcode::zadd | asmcode::zaddi => (asmcode::zaddi, false),
+        asmcode::zaddu | asmcode::zaddiu => (asmcode::zaddiu, false),
+        asmcode::zdadd | asmcode::zdaddi => (asmcode::zdaddi, false),
+        asmcode::zdaddu | asmcode::zdaddiu => (asmcode::zdaddiu, false),
+        asmcode::zslt | asmcode::zslti => (asmcode::zslti, false),
+        asmcode::zsltu | asmcode::zsltiu => (asmcode::zsltiu, false),
+        asmcode::zand | asmcode::zandi => (asmcode::zandi, true),
+        asmcode::zor | asmcode::zori => (asmcode::zori, true),
+        asmcode::zxor | asmcode::zxori => (asmcode::zxori, true),
+        _ => return None,
+    })
+}
+
+// Register form of an ALU operation written with an immediate operand
+fn register_form(op: asmcode) -> asmcode {
+    match op {
+        asmcode::zaddi => asmcode::zadd,
+        asmcode::zaddiu => asmcode::zaddu,
+        asmcode::zdaddi => asmcode::zdadd,
+        asmcode::zdaddiu => asmcode::zdaddu,
+        asmcode::zslti => asmcode::zslt,
+        asmcode::zsltiu => asmcode::zsltu,
+        asmcode::zandi => asmcode::zand,
+        asmcode::zori => asmcode::zor,
+        asmcode::zxori => asmcode::zxor,
+        asmcode::zsll => asmcode::zsllv,
+        asmcode::zsrl => asmcode::zsrlv,
+        asmcode::zsra => asmcode::zsrav,
+        asmcode::zdsll => asmcode::zdsllv,
+        asmcode::zdsrl => asmcode::zdsrlv,
+        asmcode::zdsra => asmcode::zdsrav,
+        _ => op,
+    }
+}
+
+// rd = rs op immediate
+fn alu_immediate(op: asmcode, rd: register, rs: register, value: i32) -> Vec<Insn> {
+    let (op, value) = match op {
+        asmcode::zsub => (asmcode::zadd, value.wrapping_neg()),
+        asmcode::zsubu => (asmcode::zaddu, value.wrapping_neg()),
+        asmcode::zdsub => (asmcode::zdadd, value.wrapping_neg()),
+        asmcode::zdsubu => (asmcode::zdaddu, value.wrapping_neg()),
+        _ => (op, value),
+    };
+    match op {
+        // Shift amounts are encoded in the instruction
+        asmcode::zsll | asmcode::zsrl | asmcode::zsra => {
+            return vec![rri(op, rd, rs, value & 0x1F)];
+        }
+        asmcode::zdsll | asmcode::zdsrl | asmcode::zdsra if (0..32).contains(&value) => {
+            return vec![rri(op, rd, rs, value)];
+        }
+        asmcode::zdsll | asmcode::zdsrl | asmcode::zdsra => {
+            let op32 = match op {
+                asmcode::zdsll => asmcode::zdsll32,
+                asmcode::zdsrl => asmcode::zdsrl32,
+                _ => asmcode::zdsra32,
+            };
+            return vec![rri(op32, rd, rs, value & 0x1F)];
+        }
+        _ => {}
+    }
+    if let Some((imm_op, unsigned)) = immediate_form(op) {
+        if (unsigned && fits_u16(value)) || (!unsigned && fits_i16(value)) {
+            return vec![rri(imm_op, rd, rs, value)];
+        }
+    }
+    let mut insns = load_immediate(AT, value);
+    insns.extend(alu_register(register_form(op), rd, rs, AT));
+    insns
+}
+
+// rd = rs op rt for the operations that are macros even with register operands
+fn alu_register(op: asmcode, rd: register, rs: register, rt: register) -> Vec<Insn> {
+    use asmcode::*;
+    match op {
+        zmul => vec![rr(zmultu, rs, rt), r(zmflo, rd)],
+        zdmul => vec![rr(zdmultu, rs, rt), r(zmflo, rd)],
+        zmulo | zdmulo => {
+            let (mult, sra) = if op == zmulo {
+                (zmult, rri(zsra, rd, rd, 31))
+            } else {
+                (zdmult, rri(zdsra32, rd, rd, 31))
+            };
+            vec![
+                rr(mult, rs, rt),
+                r(zmflo, rd),
+                sra,
+                r(zmfhi, AT),
+                branch2(zbeq, rd, AT, 0, 12),
+                nop(),
+                brk(6),
+                r(zmflo, rd),
+            ]
+        }
+        zmulou | zdmulou => {
+            let mult = if op == zmulou { zmultu } else { zdmultu };
+            vec![
+                rr(mult, rs, rt),
+                r(zmfhi, AT),
+                r(zmflo, rd),
+                branch2(zbeq, AT, ZERO, 0, 12),
+                nop(),
+                brk(6),
+            ]
+        }
+        zdiv | zrem | zddiv | zdrem if rd == ZERO => {
+            let div = if matches!(op, zdiv | zrem) {
+                zdiv
+            } else {
+                zddiv
+            };
+            vec![rr(div, rs, rt)]
+        }
+        zdivu | zremu | zddivu | zdremu if rd == ZERO => {
+            let div = if matches!(op, zdivu | zremu) {
+                zdivu
+            } else {
+                zddivu
+            };
+            vec![rr(div, rs, rt)]
+        }
+        // Traps on division by zero and, for signed division, on the one quotient that overflows
+        zdiv | zrem | zddiv | zdrem => {
+            let (div, min) = if matches!(op, zdiv | zrem) {
+                (
+                    zdiv,
+                    insn(
+                        zlui,
+                        ArgFormat::fri {
+                            reg: AT,
+                            immediate: 0x8000,
+                            mem_tag: 0,
+                        },
+                    ),
+                )
+            } else {
+                (zddiv, rri(zdsll32, AT, AT, 31))
+            };
+            let result = if matches!(op, zdiv | zddiv) {
+                zmflo
+            } else {
+                zmfhi
+            };
+            let mut insns = vec![
+                rr(div, rs, rt),
+                branch2(zbne, rt, ZERO, 0, 12),
+                nop(),
+                brk(7),
+                rri(zaddiu, AT, ZERO, -1),
+                branch2(zbne, rt, AT, 0, 20),
+                min,
+                branch2(zbne, rs, AT, 0, 12),
+                nop(),
+                brk(6),
+                r(result, rd),
+            ];
+            if div == zddiv {
+                // The 64-bit minimum takes two instructions, the first in the delay slot
+                insns[5] = branch2(zbne, rt, AT, 0, 24);
+                insns.insert(6, rri(zaddiu, AT, ZERO, 1));
+            }
+            insns
+        }
+        zdivu | zremu | zddivu | zdremu => {
+            let div = if matches!(op, zdivu | zremu) {
+                zdivu
+            } else {
+                zddivu
+            };
+            let result = if matches!(op, zdivu | zddivu) {
+                zmflo
+            } else {
+                zmfhi
+            };
+            vec![
+                rr(div, rs, rt),
+                branch2(zbne, rt, ZERO, 0, 12),
+                nop(),
+                brk(7),
+                r(result, rd),
+            ]
+        }
+        zrol | zror | zdrol | zdror => {
+            let (left, right, neg) = match op {
+                zrol => (zsllv, zsrlv, zsubu),
+                zror => (zsrlv, zsllv, zsubu),
+                zdrol => (zdsllv, zdsrlv, zdsubu),
+                _ => (zdsrlv, zdsllv, zdsubu),
+            };
+            vec![
+                rrr(neg, AT, ZERO, rt),
+                rrr(right, AT, rs, AT),
+                rrr(left, rd, rs, rt),
+                rrr(zor, rd, rd, AT),
+            ]
+        }
+        zseq => vec![rrr(zxor, rd, rs, rt), rri(zsltiu, rd, rd, 1)],
+        zsne => vec![rrr(zxor, rd, rs, rt), rrr(zsltu, rd, ZERO, rd)],
+        zsge => vec![rrr(zslt, rd, rs, rt), rri(zxori, rd, rd, 1)],
+        zsgeu => vec![rrr(zsltu, rd, rs, rt), rri(zxori, rd, rd, 1)],
+        zsgt => vec![rrr(zslt, rd, rt, rs)],
+        zsgtu => vec![rrr(zsltu, rd, rt, rs)],
+        zsle => vec![rrr(zslt, rd, rt, rs), rri(zxori, rd, rd, 1)],
+        zsleu => vec![rrr(zsltu, rd, rt, rs), rri(zxori, rd, rd, 1)],
+        _ => vec![rrr(op, rd, rs, rt)],
+    }
+}
+
+// Immediate forms of the set and rotate macros
+fn alu_macro_immediate(op: asmcode, rd: register, rs: register, value: i32) -> Option<Vec<Insn>> {
+    use asmcode::*;
+    Some(match op {
+        zseq if value == 0 => vec![rri(zsltiu, rd, rs, 1)],
+        zseq if fits_u16(value) => vec![rri(zxori, rd, rs, value), rri(zsltiu, rd, rd, 1)],
+        zsne if value == 0 => vec![rrr(zsltu, rd, ZERO, rs)],
+        zsne if fits_u16(value) => vec![rri(zxori, rd, rs, value), rrr(zsltu, rd, ZERO, rd)],
+        zsge if fits_i16(value) => vec![rri(zslti, rd, rs, value), rri(zxori, rd, rd, 1)],
+        zsgeu if fits_i16(value) => vec![rri(zsltiu, rd, rs, value), rri(zxori, rd, rd, 1)],
+        zsle if fits_i16(value.wrapping_add(1)) && value != i32::MAX => {
+            vec![rri(zslti, rd, rs, value + 1)]
+        }
+        zsleu if fits_i16(value.wrapping_add(1)) && value != -1 => {
+            vec![rri(zsltiu, rd, rs, value + 1)]
+        }
+        zrol | zror => {
+            let n = value & 0x1F;
+            let (left, right) = if op == zrol {
+                (zsll, zsrl)
+            } else {
+                (zsrl, zsll)
+            };
+            vec![
+                rri(right, AT, rs, (32 - n) & 0x1F),
+                rri(left, rd, rs, n),
+                rrr(zor, rd, rd, AT),
+            ]
+        }
+        zdiv | zdivu | zrem | zremu | zddiv | zddivu | zdrem | zdremu if value == 0 => {
+            vec![brk(7)]
+        }
+        // A constant divisor needs no run-time checks
+        zdiv | zdivu | zrem | zremu | zddiv | zddivu | zdrem | zdremu if rd != ZERO => {
+            let div = match op {
+                zdiv | zrem => zdiv,
+                zdivu | zremu => zdivu,
+                zddiv | zdrem => zddiv,
+                _ => zddivu,
+            };
+            let result = if matches!(op, zdiv | zdivu | zddiv | zddivu) {
+                zmflo
+            } else {
+                zmfhi
+            };
+            let mut insns = load_immediate(AT, value);
+            insns.push(rr(div, rs, AT));
+            insns.push(r(result, rd));
+            insns
+        }
+        zmul | zmulo | zmulou | zdiv | zdivu | zrem | zremu | zseq | zsne | zsge | zsgeu | zsgt
+        | zsgtu | zsle | zsleu | zdmul | zdmulo | zdmulou | zddiv | zddivu | zdrem | zdremu
+        | zdrol | zdror => {
+            let mut insns = load_immediate(AT, value);
+            insns.extend(alu_register(op, rd, rs, AT));
+            insns
+        }
+        _ => return None,
+    })
+}
+
+// Compare-and-branch macros. `rt` is None when comparing against an immediate.
+fn compare_branch(
+    op: asmcode,
+    rs: register,
+    rt: Option<register>,
+    value: i32,
+    symno: i32,
+) -> Option<Vec<Insn>> {
+    use asmcode::*;
+    // Condition, whether it is unsigned, and whether it is the likely form
+    let (cond, unsigned, likely) = match op {
+        zblt => ("lt", false, false),
+        zbltu => ("lt", true, false),
+        zbge => ("ge", false, false),
+        zbgeu => ("ge", true, false),
+        zbgt => ("gt", false, false),
+        zbgtu => ("gt", true, false),
+        zble => ("le", false, false),
+        zbleu => ("le", true, false),
+        zbltl => ("lt", false, true),
+        zbltul => ("lt", true, true),
+        zbgel => ("ge", false, true),
+        zbgeul => ("ge", true, true),
+        zbgtl => ("gt", false, true),
+        zbgtul => ("gt", true, true),
+        zblel => ("le", false, true),
+        zbleul => ("le", true, true),
+        _ => return None,
+    };
+    let (beq, bne) = if likely { (zbeql, zbnel) } else { (zbeq, zbne) };
+
+    // Signed comparisons against zero have their own instructions
+    let against_zero = rt == Some(ZERO) || (rt.is_none() && value == 0);
+    if against_zero && !unsigned {
+        let zop = match (cond, likely) {
+            ("lt", false) => zbltz,
+            ("ge", false) => zbgez,
+            ("gt", false) => zbgtz,
+            ("le", false) => zblez,
+            ("lt", true) => zbltzl,
+            ("ge", true) => zbgezl,
+            ("gt", true) => zbgtzl,
+            _ => zblezl,
+        };
+        return Some(vec![branch1(zop, rs, symno, 0)]);
+    }
+    if against_zero {
+        // Unsigned: x < 0 never holds, x >= 0 always does, x > 0 and x <= 0 test for zero
+        return Some(match cond {
+            "lt" => vec![],
+            "ge" => vec![branch2(beq, ZERO, ZERO, symno, 0)],
+            "gt" => vec![branch2(bne, rs, ZERO, symno, 0)],
+            _ => vec![branch2(beq, rs, ZERO, symno, 0)],
+        });
+    }
+
+    let slt = if unsigned { zsltu } else { zslt };
+    let slti = if unsigned { zsltiu } else { zslti };
+    let mut insns = Vec::new();
+    // Whether $at ends up set when the branch is taken
+    let taken_if_set = match rt {
+        Some(rt) => {
+            match cond {
+                "lt" | "ge" => insns.push(rrr(slt, AT, rs, rt)),
+                _ => insns.push(rrr(slt, AT, rt, rs)),
+            }
+            matches!(cond, "lt" | "gt")
+        }
+        None => {
+            // x > imm is !(x < imm + 1), x <= imm is x < imm + 1
+            let bound = if matches!(cond, "gt" | "le") {
+                value.wrapping_add(1)
+            } else {
+                value
+            };
+            if fits_i16(bound) {
+                insns.push(rri(slti, AT, rs, bound));
+            } else {
+                insns.extend(load_immediate(AT, bound));
+                insns.push(rrr(slt, AT, rs, AT));
+            }
+            matches!(cond, "lt" | "le")
+        }
+    };
+    let branch = if taken_if_set { bne } else { beq };
+    insns.push(branch2(branch, AT, ZERO, symno, 0));
+    Some(insns)
+}
+
+// Unaligned loads and stores. lwl and friends take the address of the most significant byte, which
+// is the lowest address in big-endian order and the highest in little-endian order.
+fn unaligned(op: asmcode, reg: register, offset: i16, base: register, endian: Endian) -> Vec<Insn> {
+    use asmcode::*;
+    // Offset of byte `n` of an `size`-byte value, counting from the most significant
+    let at = |n: i16, size: i16| match endian {
+        Endian::Big => offset.wrapping_add(n),
+        Endian::Little => offset.wrapping_add(size - 1 - n),
+    };
+    // A load into its own base register is assembled in $at, so that the base survives the first
+    // half of the load
+    let dest = if reg == base { AT } else { reg };
+    match op {
+        zulw | zuld => {
+            let (left, right, size) = if op == zulw {
+                (zlwl, zlwr, 4)
+            } else {
+                (zldl, zldr, 8)
+            };
+            let mut insns = vec![
+                ob(left, dest, at(0, size), base),
+                ob(right, dest, at(size - 1, size), base),
+            ];
+            if dest != reg {
+                insns.push(rrr(zor, reg, dest, ZERO));
+            }
+            insns
+        }
+        zusw => vec![ob(zswl, reg, at(0, 4), base), ob(zswr, reg, at(3, 4), base)],
+        zusd => vec![ob(zsdl, reg, at(0, 8), base), ob(zsdr, reg, at(7, 8), base)],
+        zulwu => vec![
+            ob(zlwl, dest, at(0, 4), base),
+            ob(zlwr, dest, at(3, 4), base),
+            rri(zdsll32, reg, dest, 0),
+            rri(zdsrl32, reg, reg, 0),
+        ],
+        // The high byte goes through $at first, and the low byte into `reg` once the base is no
+        // longer needed
+        zulh | zulhu if reg == base => vec![
+            ob(if op == zulh { zlb } else { zlbu }, AT, at(0, 2), base),
+            ob(zlbu, reg, at(1, 2), base),
+            rri(zsll, AT, AT, 8),
+            rrr(zor, reg, reg, AT),
+        ],
+        zulh | zulhu => vec![
+            ob(if op == zulh { zlb } else { zlbu }, reg, at(0, 2), base),
+            ob(zlbu, AT, at(1, 2), base),
+            rri(zsll, reg, reg, 8),
+            rrr(zor, reg, reg, AT),
+        ],
+        // With the address in $at there is no scratch register left, so the high byte is shifted
+        // down in `reg` itself and the low byte read back from memory to restore it
+        _ if base == AT => vec![
+            ob(zsb, reg, at(1, 2), base),
+            rri(zsrl, reg, reg, 8),
+            ob(zsb, reg, at(0, 2), base),
+            ob(zlbu, AT, at(1, 2), base),
+            rri(zsll, reg, reg, 8),
+            rrr(zor, reg, reg, AT),
+        ],
+        _ => vec![
+            ob(zsb, reg, at(1, 2), base),
+            rri(zsrl, AT, reg, 8),
+            ob(zsb, AT, at(0, 2), base),
+        ],
+    }
+}
+
+fn fp_pair(reg: register) -> register {
+    register::from_repr(reg as usize + 1).unwrap_or(reg)
+}
+
+fn expand_insns(op: asmcode, args: &ArgFormat, isa: Isa, layout: &Layout) -> Option<Vec<Insn>> {
+    use asmcode::*;
+    let original = || vec![insn(op, args.clone())];
+
+    Some(match (op, args) {
+        (zli | zdli, ArgFormat::fri { reg, immediate, .. }) => load_immediate(*reg, *immediate),
+        (zla | zdla, ArgFormat::fra { symno, reg, offset }) => {
+            load_address(*reg, *symno, *offset, ZERO, layout)
+        }
+        (
+            zla | zdla,
+            ArgFormat::frob {
+                symno,
+                reg,
+                offset,
+                base,
+                ..
+            },
+        ) => load_address(*reg, *symno, *offset as i32, *base, layout),
+        (zmove, ArgFormat::frr { reg1, reg2 }) => vec![rrr(zor, *reg1, *reg2, ZERO)],
+        (zneg, ArgFormat::frr { reg1, reg2 }) => vec![rrr(zsub, *reg1, ZERO, *reg2)],
+        (znegu, ArgFormat::frr { reg1, reg2 }) => vec![rrr(zsubu, *reg1, ZERO, *reg2)],
+        (zdneg, ArgFormat::frr { reg1, reg2 }) => vec![rrr(zdsub, *reg1, ZERO, *reg2)],
+        (zdnegu, ArgFormat::frr { reg1, reg2 }) => vec![rrr(zdsubu, *reg1, ZERO, *reg2)],
+        (znot, ArgFormat::frr { reg1, reg2 }) => vec![rrr(znor, *reg1, *reg2, ZERO)],
+        (zneg | znegu | zdneg | zdnegu | znot, ArgFormat::fr { reg }) => {
+            return expand_insns(
+                op,
+                &ArgFormat::frr {
+                    reg1: *reg,
+                    reg2: *reg,
+                },
+                isa,
+                layout,
+            )
+        }
+        (zabs | zdabs, ArgFormat::frr { reg1, reg2 }) => {
+            let (mv, neg) = if op == zabs {
+                (zaddu, zsub)
+            } else {
+                (zdaddu, zdsub)
+            };
+            vec![
+                branch1(zbgez, *reg2, 0, 12),
+                rrr(mv, *reg1, ZERO, *reg2),
+                rrr(neg, *reg1, ZERO, *reg2),
+            ]
+        }
+        (zabs | zdabs, ArgFormat::fr { reg }) => {
+            return expand_insns(
+                op,
+                &ArgFormat::frr {
+                    reg1: *reg,
+                    reg2: *reg,
+                },
+                isa,
+                layout,
+            )
+        }
+
+        (zb, ArgFormat::fl { symno }) => vec![branch2(zbeq, ZERO, ZERO, *symno, 0)],
+        (zbal, ArgFormat::fl { symno }) => vec![branch1(zbgezal, ZERO, *symno, 0)],
+        (zbeqz, ArgFormat::frl { symno, reg }) => vec![branch2(zbeq, *reg, ZERO, *symno, 0)],
+        (zbnez, ArgFormat::frl { symno, reg }) => vec![branch2(zbne, *reg, ZERO, *symno, 0)],
+        (zbeqzl, ArgFormat::frl { symno, reg }) => vec![branch2(zbeql, *reg, ZERO, *symno, 0)],
+        (zbnezl, ArgFormat::frl { symno, reg }) => vec![branch2(zbnel, *reg, ZERO, *symno, 0)],
+        (
+            zbeq | zbne | zbeql | zbnel,
+            ArgFormat::fril {
+                reg,
+                immediate,
+                symno,
+                ..
+            },
+        ) => {
+            if *immediate == 0 {
+                vec![branch2(op, *reg, ZERO, *symno, 0)]
+            } else {
+                let mut insns = load_immediate(AT, *immediate);
+                insns.push(branch2(op, *reg, AT, *symno, 0));
+                insns
+            }
+        }
+        (_, ArgFormat::frrl { symno, reg1, reg2 }) => {
+            compare_branch(op, *reg1, Some(*reg2), 0, *symno).unwrap_or_else(original)
+        }
+        (
+            _,
+            ArgFormat::fril {
+                reg,
+                immediate,
+                symno,
+                ..
+            },
+        ) => compare_branch(op, *reg, None, *immediate, *symno).unwrap_or_else(original),
+
+        (
+            zulw | zusw | zulh | zulhu | zush | zuld | zusd | zulwu,
+            ArgFormat::frob {
+                symno: 0,
+                reg,
+                offset,
+                base,
+                ..
+            },
+        ) if offset.checked_add(7).is_some() => unaligned(op, *reg, *offset, *base, layout.endian),
+        (
+            zulw | zusw | zulh | zulhu | zush | zuld | zusd | zulwu,
+            ArgFormat::fra { symno, reg, offset },
+        ) => {
+            let mut insns = load_address(AT, *symno, *offset, ZERO, layout);
+            insns.extend(unaligned(op, *reg, 0, AT, layout.endian));
+            insns
+        }
+        (
+            zulw | zusw | zulh | zulhu | zush | zuld | zusd | zulwu,
+            ArgFormat::frob {
+                symno,
+                reg,
+                offset,
+                base,
+                ..
+            },
+        ) => {
+            let mut insns = load_address(AT, *symno, *offset as i32, *base, layout);
+            insns.extend(unaligned(op, *reg, 0, AT, layout.endian));
+            insns
+        }
+
+        // Floating-point loads and stores; MIPS I moves doubles one word at a time. The odd register
+        // holds the most significant word, which is at the lower address in big-endian order.
+        (fl_d | fs_d | zldc1 | zsdc1, _) if isa == Isa::Mips1 => {
+            let word = if matches!(op, fl_d | zldc1) {
+                zlwc1
+            } else {
+                zswc1
+            };
+            let (symno, reg, offset, base) = match args {
+                ArgFormat::frob {
+                    symno,
+                    reg,
+                    offset,
+                    base,
+                    ..
+                } => (*symno, *reg, *offset as i32, *base),
+                ArgFormat::fra { symno, reg, offset } => (*symno, *reg, *offset, ZERO),
+                _ => return None,
+            };
+            let (first, second) = match layout.endian {
+                Endian::Big => (fp_pair(reg), reg),
+                Endian::Little => (reg, fp_pair(reg)),
+            };
+            memory_accesses(
+                &[(word, first, 0), (word, second, 4)],
+                AT,
+                symno,
+                offset,
+                base,
+                layout,
+            )
+        }
+        (fl_s | fs_s | fl_d | fs_d, _) => {
+            let real = match op {
+                fl_s => zlwc1,
+                fs_s => zswc1,
+                fl_d => zldc1,
+                _ => zsdc1,
+            };
+            return expand_insns(real, args, isa, layout);
+        }
+        (zmtc1_d, ArgFormat::frr { reg1, reg2 }) => vec![
+            rr(zmtc1, *reg1, *reg2),
+            rr(zmtc1, fp_pair(*reg1), fp_pair(*reg2)),
+        ],
+        (zmfc1_d, ArgFormat::frr { reg1, reg2 }) => vec![
+            rr(zmfc1, *reg1, *reg2),
+            rr(zmfc1, fp_pair(*reg1), fp_pair(*reg2)),
+        ],
+
+        // Any other load or store addressing a symbol or a 32-bit constant
+        (_, ArgFormat::fra { symno, reg, offset })
+            if matches!(
+                op_info(op).shape,
+                Shape::Load | Shape::Store | Shape::LoadMerge | Shape::StoreConditional
+            ) =>
+        {
+            memory_access(op, *reg, *symno, *offset, ZERO, layout)
+        }
+        (
+            _,
+            ArgFormat::frob {
+                symno,
+                reg,
+                offset,
+                base,
+                ..
+            },
+        ) if *symno != 0
+            && matches!(
+                op_info(op).shape,
+                Shape::Load | Shape::Store | Shape::LoadMerge | Shape::StoreConditional
+            ) =>
+        {
+            memory_access(op, *reg, *symno, *offset as i32, *base, layout)
+        }
+
+        (_, ArgFormat::frrr { reg1, reg2, reg3 })
+            if op_info(op).shape != Shape::ConditionalMove =>
+        {
+            let op = register_form(op);
+            alu_register(op, *reg1, *reg2, *reg3)
+        }
+        // Division of two registers is the machine instruction
+        (zdiv | zdivu | zddiv | zddivu, ArgFormat::frr { .. }) => original(),
+        // Two-operand ALU forms accumulate into the first register
+        (_, ArgFormat::frr { reg1, reg2 }) if op_info(op).shape == Shape::Alu => {
+            alu_register(register_form(op), *reg1, *reg1, *reg2)
+        }
+        (
+            _,
+            ArgFormat::frri {
+                reg1,
+                reg2,
+                immediate,
+                ..
+            },
+        ) if op_info(op).shape == Shape::Alu => alu_macro_immediate(op, *reg1, *reg2, *immediate)
+            .unwrap_or_else(|| alu_immediate(op, *reg1, *reg2, *immediate)),
+        (
+            _,
+            ArgFormat::frri {
+                reg1,
+                reg2,
+                immediate,
+                ..
+            },
+        ) if op_info(op).shape == Shape::MulDiv => {
+            alu_macro_immediate(op, *reg1, *reg2, *immediate)?
+        }
+        (_, ArgFormat::fri { reg, immediate, .. }) if op_info(op).shape == Shape::Alu => {
+            alu_macro_immediate(op, *reg, *reg, *immediate)
+                .unwrap_or_else(|| alu_immediate(op, *reg, *reg, *immediate))
+        }
+        (ztlt | ztltu | ztge | ztgeu | zteq | ztne, ArgFormat::fri { reg, immediate, .. }) => {
+            let mut insns = load_immediate(AT, *immediate);
+            insns.push(rr(op, *reg, AT));
+            insns
+        }
+
+        _ if op_info(op).is_macro => return None,
+        _ => original(),
+    })
+}
+
+// lw reg, %got(sym)($gp)
+fn got_load(reg: register, sym: SymRef, reloc: RelocOp) -> Insn {
+    let mut insn = ob(asmcode::zlw, reg, 0, GP);
+    insn.reloc = Reloc::Sym(SymOperand {
+        sym,
+        addend: 0,
+        reloc,
+    });
+    insn
+}
+
+// The machine instructions of `op` on the address in `tmp`, with %lo(sym+addend) added to the offset
+// of each load and store from `tmp`
+fn with_local_lo(
+    op: asmcode,
+    reg: register,
+    tmp: register,
+    operand: SymOperand,
+    isa: Isa,
+    layout: &Layout,
+) -> Option<Vec<Insn>> {
+    let access = ArgFormat::frob {
+        symno: 0,
+        reg,
+        offset: 0,
+        base: tmp,
+        mem_tag: 0,
+    };
+    let mut insns = expand_insns(op, &access, isa, layout)?;
+    for insn in &mut insns {
+        if let ArgFormat::frob { offset, base, .. } = &mut insn.args {
+            if *base == tmp && insn.reloc == Reloc::None {
+                insn.reloc = Reloc::Sym(SymOperand {
+                    addend: operand.addend.wrapping_add(*offset as i32),
+                    reloc: RelocOp::Lo,
+                    ..operand
+                });
+                *offset = 0;
+            }
+        }
+    }
+    Some(insns)
+}
+
+// addiu reg, reg, %lo(sym+addend)
+fn add_lo(reg: register, operand: SymOperand) -> Insn {
+    let mut insn = rri(asmcode::zaddiu, reg, reg, 0);
+    insn.reloc = Reloc::Sym(SymOperand {
+        reloc: RelocOp::Lo,
+        ..operand
+    });
+    insn
+}
+
+// Symbol references under .option pic: addresses are loaded from the GOT, and calls jump through
+// $25 loaded from a %call16 entry, then reload $gp from the .cprestore slot. A global symbol's GOT
+// entry holds its address, and the addend is added separately. A local symbol's entry holds the
+// address of its 64K page, and %lo(sym+addend) is added to that. Symbols the file defines without
+// .globl are local, as are .lcomm and local labels.
+fn expand_pic(
+    op: asmcode,
+    args: &ArgFormat,
+    set: &SetState,
+    isa: Isa,
+    layout: &Layout,
+) -> Option<Vec<Insn>> {
+    use asmcode::*;
+    let operand = args.sym_operand(op, set.pic)?;
+    let base = match args {
+        ArgFormat::frob { base, .. } if *base != register::xnoreg => *base,
+        _ => ZERO,
+    };
+
+    let local = layout.is_local(operand.sym);
+
+    Some(match (op, args, operand.reloc) {
+        (zjal, ArgFormat::fa { .. }, RelocOp::Call16) => {
+            let mut insns = if local {
+                vec![got_load(T9, operand.sym, RelocOp::Got), add_lo(T9, operand)]
+            } else {
+                vec![got_load(T9, operand.sym, RelocOp::Call16)]
+            };
+            insns.push(r(zjalr, T9));
+            if let (true, Some(offset)) = (set.reorder, set.cprestore) {
+                insns.push(nop());
+                insns.push(ob(zlw, GP, offset as i16, SP));
+            }
+            insns
+        }
+        (zla | zdla, ArgFormat::fra { reg, .. } | ArgFormat::frob { reg, .. }, RelocOp::Got) => {
+            let mut insns = vec![got_load(*reg, operand.sym, RelocOp::Got)];
+            if local {
+                insns.push(add_lo(*reg, operand));
+            } else if operand.addend != 0 {
+                insns.extend(alu_immediate(zaddu, *reg, *reg, operand.addend));
+            }
+            if base != ZERO {
+                insns.push(rrr(zaddu, *reg, *reg, base));
+            }
+            insns
+        }
+        (_, ArgFormat::fra { reg, .. } | ArgFormat::frob { reg, .. }, RelocOp::Got) => {
+            // As for absolute addresses, loads into a general register build the address there
+            let tmp = if *reg != ZERO
+                && (*reg as usize) < register::xfr0 as usize
+                && matches!(op_info(op).shape, Shape::Load)
+            {
+                *reg
+            } else {
+                AT
+            };
+            let mut insns = vec![got_load(tmp, operand.sym, RelocOp::Got)];
+            if base != ZERO {
+                insns.push(rrr(zaddu, tmp, tmp, base));
+            }
+            if local {
+                insns.extend(with_local_lo(op, *reg, tmp, operand, isa, layout)?);
+                return Some(insns);
+            }
+            let offset = match i16::try_from(operand.addend) {
+                Ok(offset) => offset,
+                Err(_) => {
+                    insns.extend(alu_immediate(zaddu, tmp, tmp, operand.addend));
+                    0
+                }
+            };
+            let access = ArgFormat::frob {
+                symno: 0,
+                reg: *reg,
+                offset,
+                base: tmp,
+                mem_tag: 0,
+            };
+            insns.extend(expand_insns(op, &access, isa, layout)?);
+            insns
+        }
+        _ => return None,
+    })
+}
+
+// Expands one instruction as as1 would under the .set options in effect for it. Instructions that
+// are already machine instructions come back unchanged.
+pub fn expand(
+    op: asmcode,
+    args: &ArgFormat,
+    set: &SetState,
+    isa: Isa,
+    layout: &Layout,
+) -> Expansion {
+    let mut warnings = Vec::new();
+    let insns = match expand_pic(op, args, set, isa, layout)
+        .or_else(|| expand_insns(op, args, isa, layout))
+    {
+        Some(insns) => insns,
+        None => {
+            warnings.push(Warning::NotExpanded);
+            vec![insn(op, args.clone())]
+        }
+    };
+
+    let mentions_at = |op: asmcode, args: &ArgFormat| {
+        let (defs, uses) = defs_uses(op, args);
+        defs.contains(&AT) || uses.contains(&AT)
+    };
+    if !set.at && !mentions_at(op, args) && insns.iter().any(|i| mentions_at(i.op, &i.args)) {
+        warnings.push(Warning::UsesAt);
+    }
+    if !set.macros && insns.len() > 1 {
+        warnings.push(Warning::Macro);
+    }
+    let external = |insn: &Insn| match insn.reloc {
+        Reloc::Sym(SymOperand {
+            sym: SymRef::Symbol(symno),
+            reloc: RelocOp::Hi,
+            ..
+        }) => !layout.placed.contains(&(symno as i32)),
+        _ => false,
+    };
+    if insns.iter().any(external) {
+        warnings.push(Warning::ExternalAddress);
+    }
+
+    Expansion { insns, warnings }
+}
+
+impl fmt::Display for Insn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operands = match (self.reloc, &self.args) {
+            (Reloc::None, _) => {
+                let asm = Asm::iocode {
+                    op: self.op,
+                    args: self.args.clone(),
+                };
+                return write!(f, "{asm}");
+            }
+            (Reloc::Sym(operand), ArgFormat::fri { reg, .. }) => format!("{reg}, {operand}"),
+            (Reloc::Sym(operand), ArgFormat::frob { reg, base, .. }) => {
+                format!("{reg}, {operand}({base})")
+            }
+            (Reloc::Sym(operand), ArgFormat::frri { reg1, reg2, .. }) => {
+                format!("{reg1}, {reg2}, {operand}")
+            }
+            (Reloc::Pc(pc), ArgFormat::frrl { reg1, reg2, .. }) => {
+                format!("{reg1}, {reg2}, .+{pc}")
+            }
+            (Reloc::Pc(pc), ArgFormat::frl { reg, .. }) => format!("{reg}, .+{pc}"),
+            (Reloc::Pc(pc), _) => format!(".+{pc}"),
+            // No operand slot for the symbol in this format: print the operands as they are and
+            // the relocation after them
+            (Reloc::Sym(operand), _) => {
+                let asm = Asm::iocode {
+                    op: self.op,
+                    args: self.args.clone(),
+                };
+                return write!(f, "{asm}\t# {operand}");
+            }
+        };
+        write!(f, "\t{}\t{operands}", self.op)
+    }
+}
+
+// Prints a binasm file with every macro instruction replaced by its expansion. Warnings follow the
+// instruction they concern as comments.
+pub fn print_expanded(bytes: &[u8], isa: Isa) {
+    let records = read_records(bytes);
+    let layout = Layout::collect(&records, Endian::detect(bytes));
+    for record in records {
+        let Asm::iocode { op, args } = &record.asm else {
+            println!("{}", record.asm);
+            continue;
+        };
+        let expansion = expand(*op, args, &record.set, isa, &layout);
+        for (k, insn) in expansion.insns.iter().enumerate() {
+            if record.delay_slot && k == 0 {
+                println!("\t {}", insn.to_string().trim_start_matches('\t'));
+            } else {
+                println!("{insn}");
+            }
+        }
+        for warning in &expansion.warnings {
+            println!("\t# 0x{:06X}: {warning}", record.offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::{self, *};
+    use super::*;
+    use asmcode::*;
+    use register::*;
+
+    // Every instruction of a file and the warnings it drew, expanded for MIPS II
+    fn expanded(file: &Binasm) -> (Vec<String>, Vec<Warning>) {
+        let records = file.records();
+        let layout = Layout::collect(&records, Endian::detect(file.bytes()));
+        let mut lines = Vec::new();
+        let mut warnings = Vec::new();
+        for record in &records {
+            if let Asm::iocode { op, args } = &record.asm {
+                let expansion = expand(*op, args, &record.set, Isa::Mips2, &layout);
+                lines.extend(expansion.insns.iter().map(Insn::to_string));
+                warnings.extend(expansion.warnings);
+            }
+        }
+        (lines, warnings)
+    }
+
+    #[test]
+    fn addresses_external_symbols_with_hi_and_lo() {
+        let file = Binasm::new(Endian::Big).text().ins(zla, ra(xr4, 3, 8));
+        let (lines, warnings) = expanded(&file);
+        assert_eq!(
+            lines,
+            ["\tlui\t$4, %hi(SYM_3+8)", "\taddiu\t$4, $4, %lo(SYM_3+8)"]
+        );
+        assert_eq!(warnings, [Warning::ExternalAddress]);
+    }
+
+    #[test]
+    fn addresses_small_commons_through_gp() {
+        let file = Binasm::new(Endian::Big)
+            .comm(4, 4)
+            .text()
+            .ins(zlw, ra(xr2, 4, 0));
+        let (lines, warnings) = expanded(&file);
+        assert_eq!(lines, ["\tlw\t$2, %gp_rel(SYM_4)($28)"]);
+        assert_eq!(warnings, []);
+    }
+
+    #[test]
+    fn loads_small_constants_with_addiu() {
+        for (value, line) in [
+            (5, "\taddiu\t$2, $0, 5"),
+            (-5, "\taddiu\t$2, $0, -5"),
+            (0x8000, "\tori\t$2, $0, 32768"),
+        ] {
+            let file = Binasm::new(Endian::Big).text().ins(zli, ri(xr2, value));
+            assert_eq!(expanded(&file).0, [line]);
+        }
+    }
+
+    #[test]
+    fn loads_wide_constants_in_two_halves() {
+        let file = Binasm::new(Endian::Big).text().ins(zli, ri(xr2, 0x1_2345));
+        let (lines, _) = expanded(&file);
+        assert_eq!(lines, ["\tlui\t$2, 1", "\tori\t$2, $2, 9029"]);
+    }
+
+    #[test]
+    fn stores_unaligned_halves_without_losing_the_address() {
+        let (lines, _) = expanded(&Binasm::new(Endian::Big).ins(zush, fixture::ob(xr4, 2, xr5)));
+        assert_eq!(
+            lines,
+            ["\tsb\t$4, 3($5)", "\tsrl\t$1, $4, 8", "\tsb\t$1, 2($5)"]
+        );
+        let (lines, _) = expanded(
+            &Binasm::new(Endian::Big)
+                .comm(3, 64)
+                .ins(zush, ra(xr4, 3, 0)),
+        );
+        assert_eq!(
+            lines,
+            [
+                "\tlui\t$1, %hi(SYM_3)",
+                "\taddiu\t$1, $1, %lo(SYM_3)",
+                "\tsb\t$4, 1($1)",
+                "\tsrl\t$4, $4, 8",
+                "\tsb\t$4, 0($1)",
+                "\tlbu\t$1, 1($1)",
+                "\tsll\t$4, $4, 8",
+                "\tor\t$4, $4, $1"
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_unaligned_values_into_their_own_base() {
+        let expand_one = |op, endian| {
+            let file = Binasm::new(endian).ins(op, fixture::ob(xr4, 0, xr4));
+            expanded(&file).0
+        };
+        assert_eq!(
+            expand_one(zulw, Endian::Big),
+            ["\tlwl\t$1, 0($4)", "\tlwr\t$1, 3($4)", "\tor\t$4, $1, $0"]
+        );
+        assert_eq!(
+            expand_one(zuld, Endian::Little),
+            ["\tldl\t$1, 7($4)", "\tldr\t$1, 0($4)", "\tor\t$4, $1, $0"]
+        );
+        assert_eq!(
+            expand_one(zulh, Endian::Big),
+            [
+                "\tlb\t$1, 0($4)",
+                "\tlbu\t$4, 1($4)",
+                "\tsll\t$1, $1, 8",
+                "\tor\t$4, $4, $1"
+            ]
+        );
+        // With distinct registers the load goes straight into the destination
+        let file = Binasm::new(Endian::Big).ins(zulw, fixture::ob(xr2, 0, xr4));
+        assert_eq!(expanded(&file).0, ["\tlwl\t$2, 0($4)", "\tlwr\t$2, 3($4)"]);
+    }
+
+    #[test]
+    fn leaves_machine_instructions_alone() {
+        let (lines, warnings) = expanded(&program(Endian::Big));
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "\taddiu\t$sp, $sp, -24");
+        assert_eq!(warnings, []);
+    }
+}
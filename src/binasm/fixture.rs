@@ -0,0 +1,261 @@
+// Binasm files for tests, written record by record in the layout the decoder reads
+
+use super::*;
+
+impl Endian {
+    // Stores `value` in the bitfield get_bits reads
+    fn put_bits(self, word: u32, offset: u32, count: u32, value: u32) -> u32 {
+        let shift = match self {
+            Endian::Big => 0x20 - offset - count,
+            Endian::Little => offset,
+        };
+        let mask = ((1 << count) - 1) << shift;
+        (word & !mask) | ((value << shift) & mask)
+    }
+}
+
+pub struct Binasm {
+    endian: Endian,
+    bytes: Vec<u8>,
+}
+
+impl Binasm {
+    pub fn new(endian: Endian) -> Binasm {
+        Binasm {
+            endian,
+            bytes: Vec::new(),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // The decoded records, which must all decode
+    pub fn records(&self) -> Vec<Record> {
+        let (records, errors) = decode_records(&self.bytes);
+        assert!(errors.is_empty(), "undecodable records: {errors:?}");
+        records
+    }
+
+    fn record(mut self, itype: Itype, symno: i32, word1: u32, word2: u32, word3: u32) -> Binasm {
+        let word1 = self.endian.put_bits(word1, 10, 6, itype as u32);
+        for word in [symno as u32, word1, word2, word3] {
+            self.bytes.extend(match self.endian {
+                Endian::Big => word.to_be_bytes(),
+                Endian::Little => word.to_le_bytes(),
+            });
+        }
+        self
+    }
+
+    pub fn ent(self, symno: i32) -> Binasm {
+        self.record(Itype::ient, symno, 0, 0, 0)
+    }
+
+    pub fn end(self, symno: i32) -> Binasm {
+        self.record(Itype::iend, symno, 0, 0, 0)
+    }
+
+    // A symbol's definition, or a local label's when `symno` is negative
+    pub fn label(self, symno: i32) -> Binasm {
+        self.record(Itype::ilabel, symno, 0, 0, 0)
+    }
+
+    pub fn globl(self, symno: i32) -> Binasm {
+        self.record(Itype::iglobal, symno, 0, 0, 0)
+    }
+
+    pub fn text(self) -> Binasm {
+        self.record(Itype::itext, 0, 0, 0, 0)
+    }
+
+    pub fn data(self) -> Binasm {
+        self.record(Itype::idata, 0, 0, 0, 0)
+    }
+
+    pub fn sdata(self) -> Binasm {
+        self.record(Itype::isdata, 0, 0, 0, 0)
+    }
+
+    pub fn comm(self, symno: i32, length: u32) -> Binasm {
+        self.record(Itype::icomm, symno, 0, length, 0)
+    }
+
+    pub fn word(self, symno: i32, expression: i32) -> Binasm {
+        self.record(Itype::iword, symno, 0, expression as u32, 1)
+    }
+
+    pub fn frame(self, frameoffset: i32, framereg: register, pcreg: register) -> Binasm {
+        let word3 = self.endian.put_bits(0, 0, 7, framereg as u32);
+        let word3 = self.endian.put_bits(word3, 7, 7, pcreg as u32);
+        self.record(Itype::iframe, 0, 0, frameoffset as u32, word3)
+    }
+
+    pub fn mask(self, regmask: u32, regoffset: i32) -> Binasm {
+        self.record(Itype::imask, 0, 0, regmask, regoffset as u32)
+    }
+
+    pub fn fmask(self, regmask: u32, regoffset: i32) -> Binasm {
+        self.record(Itype::ifmask, 0, 0, regmask, regoffset as u32)
+    }
+
+    pub fn livereg(self, gpmask: u32, fpmask: u32) -> Binasm {
+        self.record(Itype::ilivereg, 0, 0, gpmask, fpmask)
+    }
+
+    pub fn set(self, value: set_value) -> Binasm {
+        self.record(Itype::iset, 0, 0, value as u32, 0)
+    }
+
+    pub fn ins(self, op: asmcode, args: ArgFormat) -> Binasm {
+        let (symno, reg1, reg2, word3) = match args {
+            ArgFormat::frob {
+                symno,
+                reg,
+                offset,
+                base,
+                ..
+            } => (symno, reg, base, offset as i32),
+            ArgFormat::fra { symno, reg, offset } => (symno, reg, register::xr0, offset),
+            ArgFormat::fri { reg, immediate, .. } => (0, reg, register::xr0, immediate),
+            ArgFormat::frrr { reg1, reg2, .. } | ArgFormat::frrrr { reg1, reg2, .. } => {
+                (0, reg1, reg2, 0)
+            }
+            ArgFormat::frri {
+                reg1,
+                reg2,
+                immediate,
+                ..
+            } => (0, reg1, reg2, immediate),
+            ArgFormat::frr { reg1, reg2 } => (0, reg1, reg2, 0),
+            ArgFormat::fa {
+                symno,
+                base,
+                immediate,
+                ..
+            } => (symno, base, register::xr0, immediate),
+            ArgFormat::fr { reg } => (0, reg, register::xr0, 0),
+            ArgFormat::frrl { symno, reg1, reg2 } => (symno, reg1, reg2, 0),
+            ArgFormat::frl { symno, reg } => (symno, reg, register::xr0, 0),
+            ArgFormat::fl { symno } => (symno, register::xr0, register::xr0, 0),
+            ArgFormat::fril {
+                symno,
+                reg,
+                immediate,
+                ..
+            } => (symno, reg, register::xr0, immediate),
+            ArgFormat::fi { immediate } => (0, register::xr0, register::xr0, immediate),
+            ArgFormat::forrr | ArgFormat::foa => (0, register::xr0, register::xr0, 0),
+        };
+        let e = self.endian;
+        let mut word2 = e.put_bits(0, 0, 7, reg1 as u32);
+        word2 = e.put_bits(word2, 7, 7, reg2 as u32);
+        word2 = e.put_bits(word2, 14, 4, args.format() as u32);
+        match args {
+            ArgFormat::frrr { reg3, .. } => word2 = e.put_bits(word2, 18, 7, reg3 as u32),
+            ArgFormat::frrrr { reg3, reg4, .. } => {
+                word2 = e.put_bits(word2, 18, 7, reg3 as u32);
+                word2 = e.put_bits(word2, 25, 7, reg4 as u32);
+            }
+            _ => {
+                if let Some(mem_tag) = args.mem_tag() {
+                    word2 = e.put_bits(word2, 18, 14, mem_tag);
+                }
+            }
+        }
+        let word1 = e.put_bits(0, 22, 9, op as u32);
+        self.record(Itype::iocode, symno, word1, word2, word3 as u32)
+    }
+}
+
+// Operands, named after their formats
+
+pub fn rrr(reg1: register, reg2: register, reg3: register) -> ArgFormat {
+    ArgFormat::frrr { reg1, reg2, reg3 }
+}
+
+pub fn rri(reg1: register, reg2: register, immediate: i32) -> ArgFormat {
+    ArgFormat::frri {
+        reg1,
+        reg2,
+        immediate,
+        mem_tag: 0,
+    }
+}
+
+pub fn ri(reg: register, immediate: i32) -> ArgFormat {
+    ArgFormat::fri {
+        reg,
+        immediate,
+        mem_tag: 0,
+    }
+}
+
+pub fn r(reg: register) -> ArgFormat {
+    ArgFormat::fr { reg }
+}
+
+pub fn ob(reg: register, offset: i16, base: register) -> ArgFormat {
+    ArgFormat::frob {
+        symno: 0,
+        reg,
+        offset,
+        base,
+        mem_tag: 0,
+    }
+}
+
+// reg, sym+offset
+pub fn ra(reg: register, symno: i32, offset: i32) -> ArgFormat {
+    ArgFormat::fra { symno, reg, offset }
+}
+
+// A jump or call target
+pub fn a(symno: i32) -> ArgFormat {
+    ArgFormat::fa {
+        symno,
+        base: register::xnoreg,
+        immediate: 0,
+        mem_tag: 0,
+    }
+}
+
+pub fn rrl(reg1: register, reg2: register, symno: i32) -> ArgFormat {
+    ArgFormat::frrl { symno, reg1, reg2 }
+}
+
+pub fn none() -> ArgFormat {
+    ArgFormat::forrr
+}
+
+// main (SYM_1) passes its argument on to puts (SYM_3) in a 24-byte frame that saves $31, and helper
+// (SYM_2) returns the sum of its arguments. The frames match the procedure descriptors of
+// crate::st::fixture.
+pub fn program(endian: Endian) -> Binasm {
+    use asmcode::*;
+    use register::*;
+    Binasm::new(endian)
+        .text()
+        .globl(1)
+        .ent(1)
+        .label(1)
+        .frame(24, xr29, xr31)
+        .mask(0x8000_0000, -4)
+        .ins(zaddiu, rri(xr29, xr29, -24))
+        .ins(zsw, ob(xr31, 20, xr29))
+        .livereg(0x0800_0000, 0)
+        .ins(zjal, a(3))
+        .ins(zlw, ob(xr31, 20, xr29))
+        .ins(zaddiu, rri(xr29, xr29, 24))
+        .livereg(0x2000_0000, 0)
+        .ins(zjr, r(xr31))
+        .end(1)
+        .ent(2)
+        .label(2)
+        .frame(0, xr29, xr31)
+        .ins(zaddu, rrr(xr2, xr4, xr5))
+        .livereg(0x2000_0000, 0)
+        .ins(zjr, r(xr31))
+        .end(2)
+}
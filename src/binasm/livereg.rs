@@ -0,0 +1,357 @@
+// Compares each .livereg against register liveness computed from the instructions around it.
+//
+// ugen emits `.livereg gpmask, fpmask` before calls and returns to tell as1 which registers are live
+// at the destination of the transfer: the arguments of the callee before a jal, the return value
+// before a jr $31, and in both cases the callee-saved registers, gp, sp and fp. Both masks number
+// registers from the most significant bit: bit 31 is $0 (or $f0) and bit 0 is $31 (or $f31).
+
+use super::cfg::{build_cfg, Cfg};
+use super::opinfo::{defs_uses, op_info, Kind};
+use super::procedure::procedures;
+use super::*;
+use crate::st::SymbolTable;
+
+// One bit per general register in the low word and per FP register in the high word; the FP
+// condition and pseudo-registers are not tracked
+type RegSet = u64;
+
+fn reg_bit(reg: register) -> RegSet {
+    let idx = reg as usize;
+    if idx < 64 {
+        1 << idx
+    } else {
+        0
+    }
+}
+
+fn reg_set(regs: &[register]) -> RegSet {
+    regs.iter().fold(0, |set, reg| set | reg_bit(*reg))
+}
+
+fn livereg_set(gpmask: u32, fpmask: u32) -> RegSet {
+    (gpmask.reverse_bits() as RegSet) | ((fpmask.reverse_bits() as RegSet) << 32)
+}
+
+fn range(first: usize, last: usize) -> RegSet {
+    (first..=last).fold(0, |set, idx| set | (1 << idx))
+}
+
+// Registers with a value on entry: arguments, callee-saved registers, gp, sp and ra
+fn entry_defined() -> RegSet {
+    range(4, 7) | range(16, 23) | range(28, 31) | range(32 + 12, 32 + 15) | range(32 + 20, 32 + 31)
+}
+
+// Registers a call may overwrite: everything the callee does not have to preserve
+fn call_clobbered() -> RegSet {
+    range(1, 15) | range(24, 25) | (1 << 31) | range(32, 32 + 19)
+}
+
+// Registers every procedure preserves, and so holds live on both sides of a call or return
+fn preserved() -> RegSet {
+    range(16, 23) | range(28, 30) | range(32 + 20, 32 + 31)
+}
+
+// Registers a callee reads its arguments from
+fn arguments() -> RegSet {
+    range(4, 7) | range(32 + 12, 32 + 15)
+}
+
+// Registers a caller reads the return value from
+fn results() -> RegSet {
+    range(2, 3) | range(32, 32 + 3)
+}
+
+// Registers written and read by one record
+fn record_defs_uses(asm: &Asm) -> (RegSet, RegSet) {
+    match asm {
+        Asm::iocode { op, args } => {
+            let (defs, uses) = defs_uses(*op, args);
+            let mut defs = reg_set(&defs);
+            if op_info(*op).kind == Kind::Call {
+                defs |= call_clobbered();
+            }
+            (defs, reg_set(&uses))
+        }
+        // .cpload computes gp from the register holding the procedure's address
+        Asm::icpload {
+            args: ArgFormat::frob { reg, .. },
+            ..
+        } => (reg_bit(register::xr28), reg_bit(*reg)),
+        _ => (0, 0),
+    }
+}
+
+// Record indices of a block in execution order: a delay slot runs before the transfer it follows
+fn execution_order(cfg: &Cfg, start: usize, end: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (start..end).collect();
+    for k in 1..order.len() {
+        if cfg.records[order[k]].delay_slot {
+            order.swap(k - 1, k);
+        }
+    }
+    order
+}
+
+struct Dataflow {
+    // Registers live after each record
+    live_out: Vec<RegSet>,
+    // Registers written on some path from entry to each record, before it executes
+    defined_in: Vec<RegSet>,
+    // Registers an instruction of this procedure may have written before each record, with no call
+    // in between. A call's results are left out, as it is not known which of them it returns.
+    set_in: Vec<RegSet>,
+}
+
+// Registers a record gives a value and registers it leaves without one, for `Dataflow::set_in`
+fn record_sets(asm: &Asm) -> (RegSet, RegSet) {
+    let (defs, _) = record_defs_uses(asm);
+    match asm {
+        Asm::iocode { op, .. } if op_info(*op).kind == Kind::Call => {
+            (defs & !call_clobbered(), call_clobbered())
+        }
+        _ => (defs, 0),
+    }
+}
+
+fn dataflow(cfg: &Cfg) -> Dataflow {
+    let n = cfg.blocks.len();
+    let effects: Vec<(RegSet, RegSet)> = cfg
+        .records
+        .iter()
+        .map(|r| record_defs_uses(&r.asm))
+        .collect();
+    let orders: Vec<Vec<usize>> = cfg
+        .blocks
+        .iter()
+        .map(|b| execution_order(cfg, b.start, b.end))
+        .collect();
+    let mut predecessors = vec![Vec::new(); n];
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        for succ in &block.successors {
+            predecessors[*succ].push(b);
+        }
+    }
+
+    // Backward liveness; nothing is live after the procedure returns
+    let mut live_in = vec![0 as RegSet; n];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in (0..n).rev() {
+            let mut live = cfg.blocks[b]
+                .successors
+                .iter()
+                .fold(0, |set, succ| set | live_in[*succ]);
+            for i in orders[b].iter().rev() {
+                let (defs, uses) = effects[*i];
+                live = (live & !defs) | uses;
+            }
+            if live != live_in[b] {
+                live_in[b] = live;
+                changed = true;
+            }
+        }
+    }
+
+    // Forward may-be-defined
+    let mut defined_out = vec![0 as RegSet; n];
+    changed = true;
+    while changed {
+        changed = false;
+        for b in 0..n {
+            let mut defined = predecessors[b]
+                .iter()
+                .fold(0, |set, pred| set | defined_out[*pred]);
+            if b == 0 {
+                defined |= entry_defined();
+            }
+            for i in &orders[b] {
+                defined |= effects[*i].0;
+            }
+            if defined != defined_out[b] {
+                defined_out[b] = defined;
+                changed = true;
+            }
+        }
+    }
+
+    // Forward may-be-set
+    let sets: Vec<(RegSet, RegSet)> = cfg.records.iter().map(|r| record_sets(&r.asm)).collect();
+    let mut set_out = vec![0 as RegSet; n];
+    changed = true;
+    while changed {
+        changed = false;
+        for b in 0..n {
+            let mut set = predecessors[b]
+                .iter()
+                .fold(0, |set, pred| set | set_out[*pred]);
+            for i in &orders[b] {
+                let (gen, kill) = sets[*i];
+                set = (set & !kill) | gen;
+            }
+            if set != set_out[b] {
+                set_out[b] = set;
+                changed = true;
+            }
+        }
+    }
+
+    let mut live_out = vec![0; cfg.records.len()];
+    let mut defined_in = vec![0; cfg.records.len()];
+    let mut set_in = vec![0; cfg.records.len()];
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        let mut live = block
+            .successors
+            .iter()
+            .fold(0, |set, succ| set | live_in[*succ]);
+        for i in orders[b].iter().rev() {
+            live_out[*i] = live;
+            let (defs, uses) = effects[*i];
+            live = (live & !defs) | uses;
+        }
+
+        let mut defined = predecessors[b]
+            .iter()
+            .fold(0, |set, pred| set | defined_out[*pred]);
+        if b == 0 {
+            defined |= entry_defined();
+        }
+        for i in &orders[b] {
+            defined_in[*i] = defined;
+            defined |= effects[*i].0;
+        }
+
+        let mut set = predecessors[b]
+            .iter()
+            .fold(0, |set, pred| set | set_out[*pred]);
+        for i in &orders[b] {
+            set_in[*i] = set;
+            let (gen, kill) = sets[*i];
+            set = (set & !kill) | gen;
+        }
+    }
+
+    Dataflow {
+        live_out,
+        defined_in,
+        set_in,
+    }
+}
+
+fn register_names(set: RegSet) -> String {
+    (0..64)
+        .filter(|idx| set & (1 << idx) != 0)
+        .map(|idx| register::from_repr(idx).unwrap().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The registers a transfer's destination can read, and those of them the procedure is expected to
+// have given a value for it: the arguments of a call, the return value of a return. Any other
+// transfer stays in the procedure, where liveness after the transfer is already known.
+fn destination(op: asmcode, args: &ArgFormat, flow: &Dataflow, t: usize) -> (RegSet, RegSet) {
+    match (op_info(op).kind, args) {
+        (Kind::Call, _) => (arguments() | preserved(), flow.set_in[t] & arguments()),
+        (
+            Kind::JumpRegister,
+            ArgFormat::fr {
+                reg: register::xr31,
+            },
+        ) => (results() | preserved(), flow.set_in[t] & results()),
+        _ => {
+            let live = flow.live_out[t] & !1;
+            (live | preserved(), live)
+        }
+    }
+}
+
+// Checks every .livereg in a binasm file against the transfer that follows it. Reports registers
+// the mask claims are live that nothing could have written or that the destination cannot read,
+// and registers the destination reads that the mask leaves out. The expected set is a may-analysis:
+// an argument or result register used as scratch on some path before the transfer is expected too.
+// Returns the number of .livereg directives with a discrepancy.
+pub fn check_liveregs(bytes: &[u8], st: Option<&SymbolTable>) -> usize {
+    let mut mismatches = 0;
+
+    for proc in procedures(&read_records(bytes)) {
+        let cfg = build_cfg(&proc);
+        let flow = dataflow(&cfg);
+        let name = st
+            .and_then(|st| st.dense_name(cfg.symno as u32))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("SYM_{}", cfg.symno));
+
+        for (i, record) in cfg.records.iter().enumerate() {
+            let Asm::ilivereg { gpmask, fpmask } = record.asm else {
+                continue;
+            };
+            let Some((t, op, args)) =
+                (i + 1..cfg.records.len()).find_map(|t| match &cfg.records[t].asm {
+                    Asm::iocode { op, args } if transfer(*op).is_some() => Some((t, *op, args)),
+                    _ => None,
+                })
+            else {
+                continue;
+            };
+
+            let claimed = livereg_set(gpmask, fpmask) & !1;
+            let (readable, expected) = destination(op, args, &flow, t);
+            let undefined = claimed & !flow.defined_in[t];
+            let unread = claimed & !readable;
+            let unclaimed = expected & !claimed;
+
+            if undefined != 0 {
+                println!(
+                    "0x{:06X}: {name}: claimed live but never defined: {}",
+                    record.offset,
+                    register_names(undefined)
+                );
+            }
+            if unread != 0 {
+                println!(
+                    "0x{:06X}: {name}: claimed live but not read at the destination: {}",
+                    record.offset,
+                    register_names(unread)
+                );
+            }
+            if unclaimed != 0 {
+                println!(
+                    "0x{:06X}: {name}: read at the destination but not claimed: {}",
+                    record.offset,
+                    register_names(unclaimed)
+                );
+            }
+            if undefined != 0 || unread != 0 || unclaimed != 0 {
+                mismatches += 1;
+            }
+        }
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::*;
+    use super::*;
+    use asmcode::*;
+    use register::*;
+
+    #[test]
+    fn accepts_live_arguments_and_results() {
+        assert_eq!(check_liveregs(program(Endian::Big).bytes(), None), 0);
+    }
+
+    #[test]
+    fn reports_registers_never_defined() {
+        // $8 is claimed live at the return but nothing writes it
+        let file = Binasm::new(Endian::Big)
+            .ent(1)
+            .label(1)
+            .ins(zaddu, rrr(xr2, xr4, xr5))
+            .livereg(0x2080_0000, 0)
+            .ins(zjr, r(xr31))
+            .end(1);
+        assert_eq!(check_liveregs(file.bytes(), None), 1);
+    }
+}
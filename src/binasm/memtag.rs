@@ -0,0 +1,62 @@
+// Memory tags. ugen tags each load and store with the alias class of the memory it accesses; .mtag
+// declares a tag and .malias records that two tags may overlap. Accesses whose tags differ and are
+// not aliased are independent, which is what lets as1's scheduler reorder them.
+
+use std::collections::HashMap;
+
+use super::*;
+
+#[derive(Default)]
+pub struct MemTags {
+    // Tag number to tag type
+    declared: HashMap<u32, u32>,
+    aliases: HashMap<u32, Vec<u32>>,
+}
+
+impl MemTags {
+    // Collects the tags declared and aliased by `records`
+    pub fn collect<'a>(records: impl IntoIterator<Item = &'a Record>) -> MemTags {
+        let mut tags = MemTags::default();
+        for record in records {
+            match record.asm {
+                Asm::imtag { tagnumber, tagtype } => {
+                    tags.declared.insert(tagnumber, tagtype);
+                }
+                Asm::imalias {
+                    tagnumber1,
+                    tagnumber2,
+                } => {
+                    tags.aliases.entry(tagnumber1).or_default().push(tagnumber2);
+                    tags.aliases.entry(tagnumber2).or_default().push(tagnumber1);
+                }
+                _ => {}
+            }
+        }
+        for aliases in tags.aliases.values_mut() {
+            aliases.sort();
+            aliases.dedup();
+        }
+        tags
+    }
+
+    // Describes the memory tag of an instruction, for printing as a comment
+    pub fn comment(&self, asm: &Asm) -> Option<String> {
+        let Asm::iocode { args, .. } = asm else {
+            return None;
+        };
+        let tag = args.mem_tag()?;
+
+        let mut comment = format!("mtag {tag}");
+        if !self.declared.contains_key(&tag) {
+            comment.push_str(" (undeclared)");
+        }
+        match self.aliases.get(&tag) {
+            Some(aliases) => {
+                let aliases: Vec<String> = aliases.iter().map(|a| a.to_string()).collect();
+                comment.push_str(&format!(", may alias {}", aliases.join(", ")));
+            }
+            None => comment.push_str(", no aliases"),
+        }
+        Some(comment)
+    }
+}
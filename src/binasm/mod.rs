@@ -1,15 +1,44 @@
+mod cfg;
 mod constants;
+mod diff;
+mod disasm;
+mod encode;
+mod expand;
+#[cfg(test)]
+mod fixture;
+mod livereg;
+mod memtag;
+mod opinfo;
+mod pdr;
+mod procedure;
+mod prologue;
+mod split;
+mod validate;
 
 use std::fmt;
 use std::mem;
 
 use constants::*;
+use memtag::MemTags;
+use opinfo::{op_info, Kind};
+
+pub use cfg::print_cfgs;
+pub use diff::diff_binasm;
+pub use disasm::disassemble_elf;
+pub use encode::print_encoded;
+pub use expand::print_expanded;
+pub use livereg::check_liveregs;
+pub use opinfo::Isa;
+pub use pdr::check_pdrs;
+pub use prologue::check_prologues;
+pub use split::split_binasm;
+pub use validate::validate_binasm;
 
 const BINASM_RECORD_LENGTH: usize = 0x10;
 
 #[allow(dead_code)]
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 enum ArgFormat {
     frob {
         symno: i32,
@@ -102,7 +131,7 @@ enum ArgFormat {
 
 #[allow(dead_code)]
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 enum Asm {
     ilabel {
         symno: i32,
@@ -262,8 +291,15 @@ enum Asm {
     },
     inoalias,
     ialias,
-    imtag,
-    imalias,
+    imtag {
+        tagnumber: u32,
+        tagtype: u32,
+    },
+    // The two tags may refer to overlapping memory
+    imalias {
+        tagnumber1: u32,
+        tagnumber2: u32,
+    },
     istruct,
     ilivereg {
         gpmask: u32,
@@ -290,17 +326,106 @@ enum Asm {
     },
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct AsciiString {
     s: Vec<u8>,
 }
 
 // Floating point string, no ""
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct FPString {
     s: Vec<u8>,
 }
 
+// Assembler options set by .set, as in effect for a record, along with .option pic and the
+// procedure's .cprestore
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SetState {
+    reorder: bool,
+    at: bool,
+    macros: bool,
+    bopt: bool,
+    volatile: bool,
+    transform: bool,
+    reposition: bool,
+    // .option pic1 or pic2: symbol addresses come from the GOT and calls go through $25
+    pic: bool,
+    // Stack offset $gp is saved at, reloaded after each call in PIC code
+    cprestore: Option<u32>,
+}
+
+impl Default for SetState {
+    fn default() -> Self {
+        SetState {
+            reorder: true,
+            at: true,
+            macros: true,
+            bopt: true,
+            volatile: false,
+            transform: true,
+            reposition: true,
+            pic: false,
+            cprestore: None,
+        }
+    }
+}
+
+impl SetState {
+    fn apply(&mut self, value: set_value) {
+        match value {
+            set_value::set_reorder => self.reorder = true,
+            set_value::set_noreorder => self.reorder = false,
+            set_value::set_at => self.at = true,
+            set_value::set_noat => self.at = false,
+            set_value::set_macro => self.macros = true,
+            set_value::set_nomacro => self.macros = false,
+            set_value::set_bopt => self.bopt = true,
+            set_value::set_nobopt => self.bopt = false,
+            set_value::set_volatile => self.volatile = true,
+            set_value::set_novolatile => self.volatile = false,
+            set_value::set_transform => self.transform = true,
+            set_value::set_notransform => self.transform = false,
+            set_value::set_reposition => self.reposition = true,
+            set_value::set_noreposition => self.reposition = false,
+            set_value::set_undefined | set_value::set_move | set_value::set_nomove => {}
+        }
+    }
+}
+
+// A decoded record, with the offset it starts at and the assembler state it is read in
+#[derive(Clone, Debug)]
+struct Record {
+    offset: usize,
+    asm: Asm,
+    set: SetState,
+    // Instruction in the delay slot of a branch or jump in a noreorder region. In reorder regions
+    // as1 fills delay slots itself, so none are marked.
+    delay_slot: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Transfer {
+    // Always taken: b, j to a label
+    Unconditional,
+    // Taken or falls through
+    Conditional,
+    // Falls through once the callee returns
+    Call,
+    // Target not known statically: jr (returns, switch tables)
+    Indirect,
+}
+
+// Kind of control transfer an instruction makes, if any. These all have a delay slot.
+fn transfer(op: asmcode) -> Option<Transfer> {
+    match op_info(op).kind {
+        Kind::Jump => Some(Transfer::Unconditional),
+        Kind::JumpRegister => Some(Transfer::Indirect),
+        Kind::Call => Some(Transfer::Call),
+        Kind::Branch => Some(Transfer::Conditional),
+        Kind::Other | Kind::Load | Kind::Store => None,
+    }
+}
+
 impl fmt::Display for AsciiString {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "\"")?;
@@ -327,17 +452,11 @@ impl fmt::Display for FPString {
     }
 }
 
+#[allow(clippy::print_in_format_impl)]
 impl fmt::Display for Asm {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Asm::ilabel { symno } => {
-                if *symno > 0 {
-                    // TODO: read symbol table
-                    write!(f, "SYM_{}:", *symno)
-                } else {
-                    write!(f, "${}:", -*symno)
-                }
-            }
+            Asm::ilabel { symno } => write!(f, "{}:", SymRef::from_symno(*symno)),
             Asm::ialign { length } => {
                 write!(f, "\t.align\t{length}")
             }
@@ -391,9 +510,15 @@ impl fmt::Display for Asm {
                 linenumber,
             } => write!(f, "\t.loc\t{filenumber} {linenumber}"),
             Asm::ient { symno, lexlev } => write!(f, "\t.ent\tSYM_{symno} {lexlev}"), // TODO read symbol table
+            Asm::iend { symno } => write!(f, "\t.end\tSYM_{symno}"),
             Asm::ilivereg { gpmask, fpmask } => {
                 write!(f, "\t.livereg\t0x{gpmask:08X},0x{fpmask:08X}")
             }
+            Asm::imtag { tagnumber, tagtype } => write!(f, "\t.mtag\t{tagnumber}, {tagtype}"),
+            Asm::imalias {
+                tagnumber1,
+                tagnumber2,
+            } => write!(f, "\t.malias\t{tagnumber1}, {tagnumber2}"),
             Asm::iglobal { symno } => write!(f, "\t.globl\tSYM_{symno}"), // TODO read symbol table
             Asm::itext => write!(f, "\t.text\t"), // Strangely listed separately
             Asm::idata { symno: _ } => write!(f, "\t.data\t"),
@@ -414,106 +539,60 @@ impl fmt::Display for Asm {
                 repeat,
             } => write!(f, "\t.half\t{expression} : {repeat}"),
             Asm::iword {
-                symno,
+                symno: _,
                 expression,
                 repeat,
-            } => {
-                if *symno != 0 {
-                    if *expression == 0 && (*repeat == 0 || *repeat == 1) {
-                        if *symno > 0 {
-                            write!(f, "\t{}\tSYM_{}", ".word", *symno)
-                        } else {
-                            write!(f, "\t{}\t${}", ".word", -*symno)
-                        }
-                    } else {
-                        eprintln!("{:?}", self);
-                        unimplemented!()
-                    }
-                } else {
-                    write!(f, "\t.word\t{expression} : {repeat}")
-                }
-            }
+            } => match self.sym_operand(false) {
+                Some(operand) if *repeat > 1 => write!(f, "\t.word\t{operand} : {repeat}"),
+                Some(operand) => write!(f, "\t.word\t{operand}"),
+                None => write!(f, "\t.word\t{expression} : {repeat}"),
+            },
+            // The directive itself makes the word $gp-relative
             Asm::igpword {
-                symno,
-                expression: _,
-                repeat: _,
+                symno, expression, ..
             } => {
-                if *symno > 0 {
-                    write!(f, "\t{}\tSYM_{}", ".gpword", *symno)
-                } else {
-                    write!(f, "\t{}\t${}", ".gpword", -*symno)
-                }
+                let operand = SymOperand {
+                    sym: SymRef::from_symno(*symno),
+                    addend: *expression,
+                    reloc: RelocOp::None,
+                };
+                write!(f, "\t.gpword\t{operand}")
             }
             Asm::imask { regmask, regoffset } => write!(f, "\t.mask\t0x{regmask:08X}, {regoffset}"),
             Asm::ifmask { regmask, regoffset } => {
                 write!(f, "\t.fmask\t0x{regmask:08X}, {regoffset}")
             }
-            Asm::icpload { symno: _, args } => {
-                if let ArgFormat::frob {
-                    symno: _,
-                    reg,
-                    offset: _,
-                    base: _,
-                    mem_tag: _,
-                } = args
-                {
-                    write!(f, "\t.cpload\t{}", reg)
-                } else {
-                    unimplemented!()
+            Asm::icpload { symno: _, args } => match args {
+                ArgFormat::frob { reg, .. } | ArgFormat::fr { reg } => {
+                    write!(f, "\t.cpload\t{reg}")
                 }
-            }
+                args => write!(f, "\t.cpload\t{args:?}"),
+            },
             Asm::icprestore { offset } => {
                 write!(f, "\t.cprestore\t{offset}")
             }
-            Asm::icpadd { symno: _, args } => {
-                if let ArgFormat::frob {
-                    symno: _,
-                    reg,
-                    offset: _,
-                    base: _,
-                    mem_tag: _,
-                } = args
-                {
+            Asm::icpadd { symno: _, args } => match args {
+                ArgFormat::frob { reg, .. } | ArgFormat::fr { reg } => {
                     write!(f, "\t.cpadd\t{reg}")
-                } else {
-                    unimplemented!()
                 }
-            }
+                args => write!(f, "\t.cpadd\t{args:?}"),
+            },
             Asm::ilcomm { symno, length } => {
-                if *symno > 0 {
-                    write!(f, "\t.lcomm\tSYM_{} {length}", *symno)
-                } else {
-                    write!(f, "\t.lcomm\t${} {length}", -*symno)
-                }
+                write!(f, "\t.lcomm\t{} {length}", SymRef::from_symno(*symno))
             }
             Asm::ioption { opt, value } => {
                 write!(f, "\t.option\t{opt}{value}")
             }
             Asm::iocode { op, args } => {
                 write!(f, "\t{op}\t")?;
+                let sym = args.sym_ref();
                 match args {
                     ArgFormat::frob {
-                        symno,
-                        reg,
-                        offset,
-                        base,
-                        mem_tag: _,
-                    } => {
-                        if *symno != 0 {
-                            if *offset == 0 {
-                                if *symno > 0 {
-                                    write!(f, "{reg}, SYM_{symno}({base})")
-                                } else {
-                                    write!(f, "{reg}, ${}({base})", -*symno)
-                                }
-                            } else {
-                                println!("{:?}", self);
-                                unimplemented!()
-                            }
-                        } else {
-                            write!(f, "{reg}, {offset}({base})")
-                        }
-                    }
+                        reg, offset, base, ..
+                    } => match self.sym_operand(false) {
+                        Some(operand) => write!(f, "{reg}, {operand}({base})"),
+                        None => write!(f, "{reg}, {offset}({base})"),
+                    },
                     ArgFormat::fri {
                         reg,
                         mem_tag: _,
@@ -522,54 +601,24 @@ impl fmt::Display for Asm {
                         write!(f, "{reg}, {immediate}")
                     }
                     ArgFormat::fa {
-                        symno,
-                        base,
-                        mem_tag: _,
-                        immediate,
+                        base, immediate, ..
                     } => {
-                        if *base == register::xnoreg {
-                            if *symno == 0 {
-                                write!(f, "{immediate}")
-                            } else {
-                                if *symno > 0 {
-                                    write!(f, " SYM_{}", *symno)?;
-                                } else {
-                                    write!(f, " ${}", -*symno)?;
-                                }
-                                if *immediate != 0 {
-                                    println!("{:?}", self);
-                                    unimplemented!()
-                                } else {
-                                    write!(f, "")
-                                }
-                            }
-                        } else {
-                            println!("{:?}", self);
-                            unimplemented!()
+                        // Symbolic targets keep the leading space that existing dumps have
+                        match self.sym_operand(false) {
+                            Some(operand) => write!(f, " {operand}"),
+                            None => write!(f, "{immediate}"),
+                        }?;
+                        if *base != register::xnoreg {
+                            write!(f, "({base})")?;
                         }
+                        Ok(())
                     }
                     ArgFormat::fr { reg } => {
                         write!(f, "{reg}")
                     }
-                    ArgFormat::fl { symno } => {
-                        // TODO: read symtab
-                        if *symno > 0 {
-                            write!(f, "SYM_{}", *symno)
-                        } else {
-                            write!(f, "${}", -*symno)
-                        }
-                    }
-                    ArgFormat::fril {
-                        symno,
-                        reg,
-                        immediate,
-                        mem_tag: _,
-                    } => {
-                        if *symno > 0 {
-                            write!(f, "{reg}, {immediate}, SYM_{}", *symno)
-                        } else {
-                            write!(f, "{reg}, {immediate}, ${}", -*symno)
-                        }
+                    ArgFormat::fl { .. } => write!(f, "{sym}"),
+                    ArgFormat::fril { reg, immediate, .. } => {
+                        write!(f, "{reg}, {immediate}, {sym}")
                     }
                     ArgFormat::frr { reg1, reg2 } => {
                         write!(f, "{reg1}, {reg2}")
@@ -582,23 +631,19 @@ impl fmt::Display for Asm {
                     } => {
                         write!(f, "{reg1}, {reg2}, {immediate}")
                     }
-                    ArgFormat::fra { symno, reg, offset } => {
-                        // TODO: read from symtab
-                        if *symno > 0 {
-                            write!(f, "{reg}, SYM_{}", *symno)
-                        } else {
-                            write!(f, "{reg},  ${}", -*symno)
-                        }?;
-                        if *offset != 0 {
-                            if *offset > 0 {
-                                write!(f, "+{}", *offset)
-                            } else {
-                                write!(f, "-{}", -*offset)
-                            }
-                        } else {
-                            write!(f, "")
-                        }
+                    ArgFormat::fra { reg, offset, .. } => match self.sym_operand(false) {
+                        Some(operand) => write!(f, "{reg}, {operand}"),
+                        None => write!(f, "{reg}, {offset}"),
+                    },
+                    ArgFormat::frrr { reg1, reg2, reg3 } => {
+                        write!(f, "{reg1}, {reg2}, {reg3}")
+                    }
+                    ArgFormat::frrl { reg1, reg2, .. } => write!(f, "{reg1}, {reg2}, {sym}"),
+                    ArgFormat::frl { reg, .. } => write!(f, "{reg}, {sym}"),
+                    ArgFormat::fi { immediate } => {
+                        write!(f, "{immediate}")
                     }
+                    ArgFormat::forrr => Ok(()),
 
                     _ => write!(f, "{self:?}"),
                 }
@@ -608,36 +653,407 @@ impl fmt::Display for Asm {
     }
 }
 
-fn get_bits(word: u32, offset: u32, count: u32) -> u32 {
-    (word >> (0x20 - offset - count)) & ((1 << count) - 1)
+// What a symbol field refers to: binasm numbers symbols from 1 and local labels from -1, with 0
+// for no symbol
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum SymRef {
+    None,
+    Symbol(u32),
+    Label(u32),
 }
 
-fn process_args(bytes: &[u8], off: &mut usize) -> Option<ArgFormat> {
-    let record = &bytes[*off..*off + BINASM_RECORD_LENGTH];
-    let uwords: Vec<u32> = record
-        .chunks_exact(4)
-        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
-        .collect();
-    let iwords: Vec<i32> = record
-        .chunks_exact(4)
-        .map(|b| i32::from_be_bytes(b.try_into().unwrap()))
-        .collect();
-    let form_idx = get_bits(uwords[2], 14, 4);
-    let form = format::from_repr(form_idx as usize).unwrap();
+impl SymRef {
+    fn from_symno(symno: i32) -> SymRef {
+        match symno {
+            0 => SymRef::None,
+            s if s > 0 => SymRef::Symbol(s as u32),
+            s => SymRef::Label(s.unsigned_abs()),
+        }
+    }
+}
+
+impl fmt::Display for SymRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymRef::None => Ok(()),
+            SymRef::Symbol(n) => write!(f, "SYM_{n}"),
+            SymRef::Label(n) => write!(f, "${n}"),
+        }
+    }
+}
+
+// Relocation operator the assembler applies to a symbolic operand
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RelocOp {
+    None,
+    Hi,
+    Lo,
+    GpRel,
+    Got,
+    Call16,
+}
+
+// Symbolic operand: symbol or label, addend, and the relocation it is resolved through
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SymOperand {
+    sym: SymRef,
+    addend: i32,
+    reloc: RelocOp,
+}
+
+impl fmt::Display for SymOperand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.reloc {
+            RelocOp::None => "",
+            RelocOp::Hi => "%hi",
+            RelocOp::Lo => "%lo",
+            RelocOp::GpRel => "%gp_rel",
+            RelocOp::Got => "%got",
+            RelocOp::Call16 => "%call16",
+        };
+        let addend = match self.addend {
+            0 => String::new(),
+            a if a > 0 => format!("+{a}"),
+            a => format!("-{}", -(a as i64)),
+        };
+        match (self.sym, self.reloc) {
+            (SymRef::None, RelocOp::None) => write!(f, "{}", self.addend),
+            (SymRef::None, _) => write!(f, "{op}({})", self.addend),
+            (sym, RelocOp::None) => write!(f, "{sym}{addend}"),
+            // A GOT entry holds the symbol's own address, so the addend is applied after the load
+            (sym, RelocOp::Got | RelocOp::Call16) => write!(f, "{op}({sym}){addend}"),
+            (sym, _) => write!(f, "{op}({sym}{addend})"),
+        }
+    }
+}
+
+impl ArgFormat {
+    // The symbol or label the operands refer to
+    fn sym_ref(&self) -> SymRef {
+        match self {
+            ArgFormat::frob { symno, .. }
+            | ArgFormat::fra { symno, .. }
+            | ArgFormat::fa { symno, .. }
+            | ArgFormat::frrl { symno, .. }
+            | ArgFormat::frl { symno, .. }
+            | ArgFormat::fl { symno }
+            | ArgFormat::fril { symno, .. } => SymRef::from_symno(*symno),
+            _ => SymRef::None,
+        }
+    }
+
+    // The symbolic operand of an instruction, if it has one. Under .option pic, address loads go
+    // through the GOT, local labels included, and calls through a %call16 entry; otherwise a symbol
+    // is an absolute address that the macro expander splits into %hi and %lo.
+    fn sym_operand(&self, op: asmcode, pic: bool) -> Option<SymOperand> {
+        let sym = self.sym_ref();
+        if sym == SymRef::None {
+            return None;
+        }
+        let addend = match self {
+            ArgFormat::frob { offset, .. } => *offset as i32,
+            ArgFormat::fra { offset, .. } => *offset,
+            ArgFormat::fa { immediate, .. } => *immediate,
+            _ => 0,
+        };
+        let reloc = match self {
+            _ if !pic => RelocOp::None,
+            ArgFormat::frob { .. } | ArgFormat::fra { .. } => RelocOp::Got,
+            ArgFormat::fa { .. } if opinfo::op_info(op).kind == opinfo::Kind::Call => {
+                RelocOp::Call16
+            }
+            _ => RelocOp::None,
+        };
+        Some(SymOperand { sym, addend, reloc })
+    }
+
+    // Copy of the operands with every nonzero symbol or label number passed through `f`
+    fn map_symnos(&self, f: &mut impl FnMut(i32) -> i32) -> ArgFormat {
+        let mut args = self.clone();
+        match &mut args {
+            ArgFormat::frob { symno, .. }
+            | ArgFormat::fra { symno, .. }
+            | ArgFormat::fa { symno, .. }
+            | ArgFormat::frrl { symno, .. }
+            | ArgFormat::frl { symno, .. }
+            | ArgFormat::fl { symno }
+            | ArgFormat::fril { symno, .. }
+                if *symno != 0 =>
+            {
+                *symno = f(*symno)
+            }
+            _ => {}
+        }
+        args
+    }
+
+    // Memory tag tying a load or store to a .mtag, if it has one
+    fn mem_tag(&self) -> Option<u32> {
+        match self {
+            ArgFormat::frob { mem_tag, .. }
+            | ArgFormat::fri { mem_tag, .. }
+            | ArgFormat::frri { mem_tag, .. }
+            | ArgFormat::fa { mem_tag, .. }
+            | ArgFormat::fril { mem_tag, .. }
+                if *mem_tag != 0 =>
+            {
+                Some(*mem_tag)
+            }
+            _ => None,
+        }
+    }
+
+    // The format field these operands were decoded from
+    fn format(&self) -> format {
+        match self {
+            ArgFormat::frob { .. } => format::frob,
+            ArgFormat::fra { .. } => format::fra,
+            ArgFormat::fri { .. } => format::fri,
+            ArgFormat::frrr { .. } => format::frrr,
+            ArgFormat::frri { .. } => format::frri,
+            ArgFormat::frr { .. } => format::frr,
+            ArgFormat::fa { .. } => format::fa,
+            ArgFormat::fr { .. } => format::fr,
+            ArgFormat::frrl { .. } => format::frrl,
+            ArgFormat::frl { .. } => format::frl,
+            ArgFormat::fl { .. } => format::fl,
+            ArgFormat::forrr => format::forrr,
+            ArgFormat::fril { .. } => format::fril,
+            ArgFormat::fi { .. } => format::fi,
+            ArgFormat::foa => format::foa,
+            ArgFormat::frrrr { .. } => format::frrrr,
+        }
+    }
+}
+
+impl Asm {
+    // The symbolic operand of an instruction or data word, if it has one, with the relocation the
+    // assembler resolves it through
+    fn sym_operand(&self, pic: bool) -> Option<SymOperand> {
+        match self {
+            Asm::iocode { op, args } => args.sym_operand(*op, pic),
+            Asm::iword {
+                symno, expression, ..
+            }
+            | Asm::igpword {
+                symno, expression, ..
+            } if *symno != 0 => Some(SymOperand {
+                sym: SymRef::from_symno(*symno),
+                addend: *expression,
+                reloc: match self {
+                    Asm::igpword { .. } => RelocOp::GpRel,
+                    _ => RelocOp::None,
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    // Copy of the record with every nonzero symbol or label number passed through `f`, including
+    // those in instruction operands
+    fn map_symnos(&self, f: &mut impl FnMut(i32) -> i32) -> Asm {
+        let mut asm = self.clone();
+        match &mut asm {
+            Asm::icpload { symno, args } | Asm::icpadd { symno, args } => {
+                if *symno != 0 {
+                    *symno = f(*symno);
+                }
+                *args = args.map_symnos(f);
+            }
+            Asm::iocode { op: _, args } => *args = args.map_symnos(f),
+            Asm::ilabel { symno }
+            | Asm::iglobal { symno }
+            | Asm::ibyte { symno, .. }
+            | Asm::icomm { symno, .. }
+            | Asm::ilcomm { symno, .. }
+            | Asm::idata { symno }
+            | Asm::ihalf { symno, .. }
+            | Asm::igpword { symno, .. }
+            | Asm::iweakext { symno, .. }
+            | Asm::iword { symno, .. }
+            | Asm::iend { symno }
+            | Asm::isdata { symno }
+            | Asm::irdata { symno }
+            | Asm::ient { symno, .. }
+            | Asm::ibgnb { symno }
+            | Asm::iendb { symno }
+            | Asm::iasm0 { symno }
+            | Asm::iendrep { symno }
+            | Asm::ilab { symno }
+            | Asm::iaent { symno, .. }
+            | Asm::irestext { symno }
+            | Asm::iprologue { symno, .. }
+            | Asm::ialloc { symno }
+                if *symno != 0 =>
+            {
+                *symno = f(*symno)
+            }
+            _ => {}
+        }
+        asm
+    }
+}
+
+// Replaces each SYM_n in printed binasm with the name `name` gives symbol n, if any
+fn replace_symbol_names<'a>(text: &str, name: impl Fn(usize) -> Option<&'a str>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find("SYM_") {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos + 4..];
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        match rest[..digits].parse::<usize>().ok().and_then(&name) {
+            Some(name) => out.push_str(name),
+            None => {
+                out.push_str("SYM_");
+                out.push_str(&rest[..digits]);
+            }
+        }
+        rest = &rest[digits..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Byte order of a binasm file. Big-endian compilers (IRIX) allocate bitfields from the most
+// significant bit and little-endian ones (DECstation Ultrix) from the least, so the order decides
+// both how words are read and where fields sit in them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+impl Endian {
+    fn u32(self, b: &[u8]) -> u32 {
+        let b = b.try_into().unwrap();
+        match self {
+            Endian::Big => u32::from_be_bytes(b),
+            Endian::Little => u32::from_le_bytes(b),
+        }
+    }
+
+    fn i32(self, b: &[u8]) -> i32 {
+        self.u32(b) as i32
+    }
+
+    // Bitfield `count` bits wide, `offset` bits from the start of allocation
+    fn get_bits(self, word: u32, offset: u32, count: u32) -> u32 {
+        let shift = match self {
+            Endian::Big => 0x20 - offset - count,
+            Endian::Little => offset,
+        };
+        (word >> shift) & ((1 << count) - 1)
+    }
+
+    // Guesses the byte order of a binasm file, which does not record it. Each order scores a point
+    // for every symbol number or immediate word that reads as a small number, since those read as
+    // large ones in the wrong order, and for every instruction record whose opcode, operand format
+    // and registers are all valid. Every record is scored. The guess can still go wrong:
+    // - a file of mostly data records with large values gives both orders similar scores
+    // - string and float payloads are scored as if they were records
+    // - ties, such as an empty file, go to big-endian, the order IRIX uses
+    pub fn detect(bytes: &[u8]) -> Endian {
+        let score = |endian: Endian| {
+            let records = bytes.chunks_exact(BINASM_RECORD_LENGTH);
+            let small_words = records
+                .clone()
+                .flat_map(|record| [&record[0..4], &record[12..16]])
+                .filter(|b| endian.i32(b).unsigned_abs() < 0x10000)
+                .count();
+            let instructions = records
+                .filter(|record| {
+                    let word1 = endian.u32(&record[4..8]);
+                    let word2 = endian.u32(&record[8..12]);
+                    Itype::from_repr(endian.get_bits(word1, 10, 6) as usize) == Some(Itype::iocode)
+                        && asmcode::from_repr(endian.get_bits(word1, 22, 9) as usize).is_some()
+                        && format::from_repr(endian.get_bits(word2, 14, 4) as usize).is_some()
+                        && register::from_repr(endian.get_bits(word2, 0, 7) as usize).is_some()
+                        && register::from_repr(endian.get_bits(word2, 7, 7) as usize).is_some()
+                })
+                .count();
+            small_words + instructions
+        };
+        if score(Endian::Little) > score(Endian::Big) {
+            Endian::Little
+        } else {
+            Endian::Big
+        }
+    }
+}
+
+// Why a record could not be decoded: a field outside the values this decoder knows, or a record
+// or its payload running past the end of the file. Either points at a misaligned stream or a
+// binasm layout from a different compiler release.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DecodeError {
+    Truncated,
+    Itype(u32),
+    Opcode(u32),
+    Format(u32),
+    Register(u32),
+    Set(u32),
+    Option(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "record runs past the end of the file"),
+            DecodeError::Itype(n) => write!(f, "unknown record type {n}"),
+            DecodeError::Opcode(n) => write!(f, "unknown opcode {n}"),
+            DecodeError::Format(n) => write!(f, "unknown operand format {n}"),
+            DecodeError::Register(n) => write!(f, "unknown register {n}"),
+            DecodeError::Set(n) => write!(f, "unknown .set value {n}"),
+            DecodeError::Option(n) => write!(f, "unknown .option {n}"),
+        }
+    }
+}
+
+fn record_at(bytes: &[u8], off: usize) -> Result<&[u8], DecodeError> {
+    bytes
+        .get(off..off + BINASM_RECORD_LENGTH)
+        .ok_or(DecodeError::Truncated)
+}
+
+// String or number text that follows a record
+fn payload(bytes: &[u8], off: usize, length: u32) -> Result<&[u8], DecodeError> {
+    let start = off + BINASM_RECORD_LENGTH;
+    bytes
+        .get(start..start + length as usize)
+        .ok_or(DecodeError::Truncated)
+}
+
+fn decode_register(idx: u32) -> Result<register, DecodeError> {
+    register::from_repr(idx as usize).ok_or(DecodeError::Register(idx))
+}
+
+// Word 2 of an instruction record holds reg1 in bits 0-6, reg2 in 7-13 and the format in 14-17,
+// counted from the start of allocation. The remaining 14 bits are the memory tag of memory
+// operands, or reg3 (18-24) and reg4 (25-31) of three- and four-register formats.
+#[allow(clippy::redundant_locals)]
+fn process_args(bytes: &[u8], off: &mut usize, endian: Endian) -> Result<ArgFormat, DecodeError> {
+    let record = record_at(bytes, *off)?;
+    let uwords: Vec<u32> = record.chunks_exact(4).map(|b| endian.u32(b)).collect();
+    let iwords: Vec<i32> = record.chunks_exact(4).map(|b| endian.i32(b)).collect();
+    let form_idx = endian.get_bits(uwords[2], 14, 4);
+    let form = format::from_repr(form_idx as usize).ok_or(DecodeError::Format(form_idx))?;
 
     let symno = iwords[0];
-    let reg1_idx = get_bits(uwords[2], 0, 7);
-    let reg1 = register::from_repr(reg1_idx as usize).unwrap();
-    let reg2_idx = get_bits(uwords[2], 7, 7);
-    let reg2 = register::from_repr(reg2_idx as usize).unwrap();
-    let mem_tag = get_bits(uwords[2], 18, 14);
+    let reg1_idx = endian.get_bits(uwords[2], 0, 7);
+    let reg1 = decode_register(reg1_idx)?;
+    let reg2_idx = endian.get_bits(uwords[2], 7, 7);
+    let reg2 = decode_register(reg2_idx)?;
+    let mem_tag = endian.get_bits(uwords[2], 18, 14);
 
     match form {
         format::frob => {
             let reg = reg1;
             let base = reg2;
             let offset = iwords[3] as i16;
-            Some(ArgFormat::frob {
+            Ok(ArgFormat::frob {
                 symno,
                 reg,
                 offset,
@@ -649,14 +1065,14 @@ fn process_args(bytes: &[u8], off: &mut usize) -> Option<ArgFormat> {
             let reg = reg1;
             let offset = iwords[3];
             // println!("{:?}", record);
-            Some(ArgFormat::fra { symno, reg, offset })
+            Ok(ArgFormat::fra { symno, reg, offset })
         }
         format::fri => {
             let reg = reg1;
-            let mem_tag = get_bits(uwords[2], 18, 14);
+            let mem_tag = endian.get_bits(uwords[2], 18, 14);
             let immediate = iwords[3];
 
-            Some(ArgFormat::fri {
+            Ok(ArgFormat::fri {
                 reg,
                 mem_tag,
                 immediate,
@@ -665,32 +1081,32 @@ fn process_args(bytes: &[u8], off: &mut usize) -> Option<ArgFormat> {
         format::frrr => {
             let reg1 = reg1;
             let reg2 = reg2;
-            let reg3_idx = get_bits(uwords[2], 14, 7);
-            let reg3 = register::from_repr(reg3_idx as usize).unwrap();
+            let reg3_idx = endian.get_bits(uwords[2], 18, 7);
+            let reg3 = decode_register(reg3_idx)?;
 
-            Some(ArgFormat::frrr { reg1, reg2, reg3 })
+            Ok(ArgFormat::frrr { reg1, reg2, reg3 })
         }
         format::frri => {
             let reg1 = reg1;
             let reg2 = reg2;
             let immediate = iwords[3];
-            let mem_tag = get_bits(uwords[2], 18, 14);
+            let mem_tag = endian.get_bits(uwords[2], 18, 14);
 
-            Some(ArgFormat::frri {
+            Ok(ArgFormat::frri {
                 reg1,
                 reg2,
                 immediate,
                 mem_tag,
             })
         }
-        format::frr => Some(ArgFormat::frr { reg1, reg2 }),
+        format::frr => Ok(ArgFormat::frr { reg1, reg2 }),
         format::fa => {
             // println!("{:?}", record);
             let base = reg1;
-            let mem_tag = get_bits(uwords[2], 18, 14);
+            let mem_tag = endian.get_bits(uwords[2], 18, 14);
             let immediate = iwords[3];
 
-            Some(ArgFormat::fa {
+            Ok(ArgFormat::fa {
                 symno,
                 base,
                 mem_tag,
@@ -700,20 +1116,20 @@ fn process_args(bytes: &[u8], off: &mut usize) -> Option<ArgFormat> {
         format::fr => {
             let reg = reg1;
 
-            Some(ArgFormat::fr { reg })
+            Ok(ArgFormat::fr { reg })
         }
-        format::frrl => Some(ArgFormat::frrl { symno, reg1, reg2 }),
+        format::frrl => Ok(ArgFormat::frrl { symno, reg1, reg2 }),
         format::frl => {
             let reg = reg1;
-            Some(ArgFormat::frl { symno, reg })
+            Ok(ArgFormat::frl { symno, reg })
         }
-        format::fl => Some(ArgFormat::fl { symno }),
-        format::forrr => Some(ArgFormat::forrr),
+        format::fl => Ok(ArgFormat::fl { symno }),
+        format::forrr => Ok(ArgFormat::forrr),
         format::fril => {
             let reg = reg1;
             let immediate = iwords[3];
 
-            Some(ArgFormat::fril {
+            Ok(ArgFormat::fril {
                 reg,
                 immediate,
                 symno,
@@ -723,18 +1139,18 @@ fn process_args(bytes: &[u8], off: &mut usize) -> Option<ArgFormat> {
         format::fi => {
             let immediate = iwords[3];
 
-            Some(ArgFormat::fi { immediate })
+            Ok(ArgFormat::fi { immediate })
         }
-        format::foa => Some(ArgFormat::foa),
+        format::foa => Ok(ArgFormat::foa),
         format::frrrr => {
             let reg1 = reg1;
             let reg2 = reg2;
-            let reg3_idx = get_bits(uwords[2], 14, 7);
-            let reg3 = register::from_repr(reg3_idx as usize).unwrap();
-            let reg4_idx = get_bits(uwords[2], 21, 7);
-            let reg4 = register::from_repr(reg4_idx as usize).unwrap();
+            let reg3_idx = endian.get_bits(uwords[2], 18, 7);
+            let reg3 = decode_register(reg3_idx)?;
+            let reg4_idx = endian.get_bits(uwords[2], 25, 7);
+            let reg4 = decode_register(reg4_idx)?;
 
-            Some(ArgFormat::frrrr {
+            Ok(ArgFormat::frrrr {
                 reg1,
                 reg2,
                 reg3,
@@ -744,24 +1160,27 @@ fn process_args(bytes: &[u8], off: &mut usize) -> Option<ArgFormat> {
     }
 }
 
-fn process_iocode(bytes: &[u8], off: &mut usize) -> Option<Asm> {
+#[allow(clippy::needless_late_init)]
+fn process_iocode(
+    bytes: &[u8],
+    off: &mut usize,
+    endian: Endian,
+) -> Result<Option<Asm>, DecodeError> {
     let asm; // = None;
-    let record = &bytes[*off..*off + BINASM_RECORD_LENGTH];
-    let uwords: Vec<u32> = record
-        .chunks_exact(4)
-        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
-        .collect();
-    // let iwords: Vec<i32> = record.chunks_exact(4).map(|b| i32::from_be_bytes(b.try_into().unwrap())).collect();
-    let op_idx = get_bits(uwords[1], 31 - 9, 9);
-    let op = asmcode::from_repr(op_idx as usize).unwrap();
-    let args = process_args(bytes, off).unwrap();
+    let record = record_at(bytes, *off)?;
+    let uwords: Vec<u32> = record.chunks_exact(4).map(|b| endian.u32(b)).collect();
+    // let iwords: Vec<i32> = record.chunks_exact(4).map(|b| endian.i32(b)).collect();
+    let op_idx = endian.get_bits(uwords[1], 31 - 9, 9);
+    let op = asmcode::from_repr(op_idx as usize).ok_or(DecodeError::Opcode(op_idx))?;
+    let args = process_args(bytes, off, endian)?;
 
     asm = Some(Asm::iocode { op, args });
 
-    asm
+    Ok(asm)
 }
 
-#[derive(Debug)]
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
 enum OptValue {
     None,
     Int(i32),
@@ -780,20 +1199,23 @@ impl fmt::Display for OptValue {
     }
 }
 
-fn process_ioption(bytes: &[u8], off: &mut usize) -> Option<Asm> {
+#[allow(clippy::needless_late_init)]
+fn process_ioption(
+    bytes: &[u8],
+    off: &mut usize,
+    endian: Endian,
+) -> Result<Option<Asm>, DecodeError> {
     let asm; // = None;
-    let record = &bytes[*off..*off + BINASM_RECORD_LENGTH];
+    let record = record_at(bytes, *off)?;
     let uwords: Vec<u32> = record
         .chunks_exact(mem::size_of::<u32>())
-        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
-        .collect();
-    let iwords: Vec<i32> = record
-        .chunks_exact(4)
-        .map(|b| i32::from_be_bytes(b.try_into().unwrap()))
+        .map(|b| endian.u32(b))
         .collect();
+    let iwords: Vec<i32> = record.chunks_exact(4).map(|b| endian.i32(b)).collect();
 
-    let option_idx = get_bits(uwords[1], 16, 2);
-    let option_type = opt_type::from_repr(option_idx as usize).unwrap();
+    let option_idx = endian.get_bits(uwords[1], 16, 2);
+    let option_type =
+        opt_type::from_repr(option_idx as usize).ok_or(DecodeError::Option(option_idx))?;
 
     let option_arg_type = match option_type {
         opt_type::o_undefined => opt_arg_type::opt_none,
@@ -822,23 +1244,29 @@ fn process_ioption(bytes: &[u8], off: &mut usize) -> Option<Asm> {
         opt_arg_type::opt_float | opt_arg_type::opt_string => unimplemented!(),
     }
 
-    asm
+    Ok(asm)
 }
 
-fn process_record(bytes: &[u8], off: &mut usize) -> Option<Asm> {
+#[allow(
+    unreachable_patterns,
+    clippy::match_single_binding,
+    clippy::manual_div_ceil
+)]
+fn process_record(
+    bytes: &[u8],
+    off: &mut usize,
+    endian: Endian,
+) -> Result<Option<Asm>, DecodeError> {
     let mut asm = None;
-    let record = &bytes[*off..*off + BINASM_RECORD_LENGTH];
+    let record = record_at(bytes, *off)?;
     let uwords: Vec<u32> = record
         .chunks_exact(mem::size_of::<u32>())
-        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
-        .collect();
-    let iwords: Vec<i32> = record
-        .chunks_exact(4)
-        .map(|b| i32::from_be_bytes(b.try_into().unwrap()))
+        .map(|b| endian.u32(b))
         .collect();
+    let iwords: Vec<i32> = record.chunks_exact(4).map(|b| endian.i32(b)).collect();
 
-    let t = get_bits(uwords[1], 10, 6) as usize;
-    let itype = Itype::from_repr(t as usize).unwrap();
+    let t = endian.get_bits(uwords[1], 10, 6);
+    let itype = Itype::from_repr(t as usize).ok_or(DecodeError::Itype(t))?;
     // println!("{t:?} -> {itype:?}");
     let symno = iwords[0];
     let lexlev = iwords[2];
@@ -867,10 +1295,10 @@ fn process_record(bytes: &[u8], off: &mut usize) -> Option<Asm> {
         Itype::iweakext => asm = Some(Asm::iweakext { symno, lexlev }),
 
         Itype::iframe => {
-            let frameoffset = i32::from_be_bytes(record[8..0xC].try_into().unwrap());
-            let word3 = u32::from_be_bytes(record[0xC..0x10].try_into().unwrap());
-            let framereg = register::from_repr(get_bits(word3, 0, 7) as usize).unwrap();
-            let pcreg = register::from_repr(get_bits(word3, 7, 7) as usize).unwrap();
+            let frameoffset = endian.i32(&record[8..0xC]);
+            let word3 = endian.u32(&record[0xC..0x10]);
+            let framereg = decode_register(endian.get_bits(word3, 0, 7))?;
+            let pcreg = decode_register(endian.get_bits(word3, 7, 7))?;
 
             asm = Some(Asm::iframe {
                 frameoffset,
@@ -912,13 +1340,12 @@ fn process_record(bytes: &[u8], off: &mut usize) -> Option<Asm> {
             asm = Some(Asm::ialign { length });
         }
         Itype::iset => {
-            let value = set_value::from_repr(length as usize).unwrap();
+            let value = set_value::from_repr(length as usize).ok_or(DecodeError::Set(length))?;
             asm = Some(Asm::iset { value });
         }
         Itype::iascii | Itype::iasciiz | Itype::ifile => {
             let mut string = AsciiString { s: Vec::new() };
-            bytes[*off + BINASM_RECORD_LENGTH..*off + BINASM_RECORD_LENGTH + length as usize]
-                .clone_into(&mut string.s);
+            payload(bytes, *off, length)?.clone_into(&mut string.s);
             asm = Some(match itype {
                 Itype::iascii => Asm::iascii {
                     length,
@@ -953,8 +1380,7 @@ fn process_record(bytes: &[u8], off: &mut usize) -> Option<Asm> {
         Itype::ifloat | Itype::idouble | Itype::iextended => {
             let repeat = uwords[3];
             let mut string = FPString { s: Vec::new() };
-            bytes[*off + BINASM_RECORD_LENGTH..*off + BINASM_RECORD_LENGTH + length as usize]
-                .clone_into(&mut string.s);
+            payload(bytes, *off, length)?.clone_into(&mut string.s);
 
             asm = match itype {
                 Itype::ifloat => Some(Asm::ifloat {
@@ -1008,6 +1434,19 @@ fn process_record(bytes: &[u8], off: &mut usize) -> Option<Asm> {
             let fpmask = uwords[3];
             asm = Some(Asm::ilivereg { gpmask, fpmask });
         }
+        Itype::imtag => {
+            let tagnumber = uwords[2];
+            let tagtype = uwords[3];
+            asm = Some(Asm::imtag { tagnumber, tagtype });
+        }
+        Itype::imalias => {
+            let tagnumber1 = uwords[2];
+            let tagnumber2 = uwords[3];
+            asm = Some(Asm::imalias {
+                tagnumber1,
+                tagnumber2,
+            });
+        }
         Itype::idword => {
             let expression = iwords[2];
 
@@ -1052,42 +1491,327 @@ fn process_record(bytes: &[u8], off: &mut usize) -> Option<Asm> {
         }
 
         Itype::iocode => {
-            asm = process_iocode(bytes, off);
+            asm = process_iocode(bytes, off, endian)?;
         }
         Itype::icpload => {
-            let args = process_args(bytes, off).unwrap();
+            let args = process_args(bytes, off, endian)?;
             asm = Some(Asm::icpload { symno, args });
         }
         Itype::icpadd => {
-            let args = process_args(bytes, off).unwrap();
+            let args = process_args(bytes, off, endian)?;
             asm = Some(Asm::icpadd { symno, args });
         }
-        Itype::ivreg | Itype::icpload | Itype::icpalias | Itype::icpadd => {
+        Itype::ivreg | Itype::icpalias => {
             // print!("{:?} ", itype);
-            // asm = process_iocode(bytes, off);
+            // asm = process_iocode(bytes, off, endian);
         }
-        Itype::ioption => asm = process_ioption(bytes, off),
+        Itype::ioption => asm = process_ioption(bytes, off, endian)?,
         _ => {}
     }
 
-    if asm.is_some() {
-        println!("{}", asm.as_ref().unwrap());
-        return asm;
+    Ok(asm)
+}
+
+fn print_record(
+    bytes: &[u8],
+    off: usize,
+    decoded: &Result<Option<Record>, DecodeError>,
+    tags: &MemTags,
+    endian: Endian,
+) {
+    if let Ok(Some(Record {
+        asm,
+        set,
+        delay_slot,
+        ..
+    })) = decoded
+    {
+        let pic = set.pic;
+        let mut line = asm.to_string();
+        if *delay_slot {
+            // Delay slots are indented by an extra space
+            line = format!("\t {}", line.trim_start_matches('\t'));
+        }
+        let mut comments: Vec<String> = tags.comment(asm).into_iter().collect();
+        // In PIC code, show how each symbol reference is resolved
+        if let Some(operand) = asm
+            .sym_operand(pic)
+            .filter(|operand| pic && operand.reloc != RelocOp::None)
+        {
+            comments.push(operand.to_string());
+        }
+        if comments.is_empty() {
+            println!("{line}");
+        } else {
+            println!("{line}\t# {}", comments.join("; "));
+        }
+        return;
+    }
+    let record = &bytes[off..(off + BINASM_RECORD_LENGTH).min(bytes.len())];
+    let itype = record
+        .get(4..8)
+        .and_then(|word1| Itype::from_repr(endian.get_bits(endian.u32(word1), 10, 6) as usize));
+    match itype {
+        Some(itype) => print!("{:12}: ", format!("{itype:?}")),
+        None => print!("{:12}: ", "?"),
     }
-    print!("{:12}: ", format!("{itype:?}"));
     for b in record {
         print!("{b:02X} ");
     }
-    println!();
-    None
+    match decoded {
+        Err(err) => println!("# {err}"),
+        Ok(_) => println!(),
+    }
 }
 
-// Returns number of bytes read
-pub fn process_records(bytes: &[u8]) -> usize {
+// Follows .set directives and branches through the record stream
+#[derive(Default)]
+struct SetTracker {
+    set: SetState,
+    after_transfer: bool,
+}
+
+impl SetTracker {
+    // State in effect for `asm`, and whether it is in a delay slot
+    fn next(&mut self, asm: &Asm) -> (SetState, bool) {
+        let set = self.set;
+        let mut delay_slot = false;
+        match asm {
+            Asm::iset { value } => self.set.apply(*value),
+            Asm::ioption {
+                opt: opt_type::o_pic,
+                value,
+            } => self.set.pic = matches!(value, OptValue::Int(level) if *level != 0),
+            Asm::ient { .. } => self.set.cprestore = None,
+            Asm::icprestore { offset } => self.set.cprestore = Some(*offset),
+            Asm::iocode { op, args: _ } => {
+                delay_slot = self.after_transfer;
+                self.after_transfer = !delay_slot && !set.reorder && transfer(*op).is_some();
+            }
+            _ => {}
+        }
+        (set, delay_slot)
+    }
+}
+
+// Decodes the record at each record offset: the record, None for a record that is not decoded yet,
+// or the reason it could not be decoded. Decoding resumes at the next record after an error.
+fn decode(bytes: &[u8], endian: Endian) -> Vec<(usize, Result<Option<Record>, DecodeError>)> {
+    let mut decoded = Vec::new();
+    let mut tracker = SetTracker::default();
     let mut off = 0;
     while off < bytes.len() {
-        process_record(bytes, &mut off);
+        let offset = off;
+        let record = process_record(bytes, &mut off, endian).map(|asm| {
+            asm.map(|asm| {
+                let (set, delay_slot) = tracker.next(&asm);
+                Record {
+                    offset,
+                    asm,
+                    set,
+                    delay_slot,
+                }
+            })
+        });
+        decoded.push((offset, record));
         off += BINASM_RECORD_LENGTH;
     }
-    return off;
+    decoded
+}
+
+// Decodes every record, along with the offset and reason of each record that could not be
+// decoded. Records that cannot be decoded yet are skipped.
+fn decode_records(bytes: &[u8]) -> (Vec<Record>, Vec<(usize, DecodeError)>) {
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    for (offset, record) in decode(bytes, Endian::detect(bytes)) {
+        match record {
+            Ok(Some(record)) => records.push(record),
+            Ok(None) => {}
+            Err(err) => errors.push((offset, err)),
+        }
+    }
+    (records, errors)
+}
+
+// Decodes every record, warning on stderr about those that could not be decoded
+fn read_records(bytes: &[u8]) -> Vec<Record> {
+    let (records, errors) = decode_records(bytes);
+    for (off, err) in errors {
+        eprintln!("0x{off:06X}: {err}");
+    }
+    records
+}
+
+// Returns number of bytes read
+pub fn process_records(bytes: &[u8]) -> usize {
+    let endian = Endian::detect(bytes);
+    let decoded = decode(bytes, endian);
+
+    // Tags are declared per procedure, possibly after their first use, so each procedure's
+    // instructions are described with the tags declared from its .ent up to the next one
+    let mut start = 0;
+    while start < decoded.len() {
+        let end = (start + 1..decoded.len())
+            .find(|i| matches!(&decoded[*i].1, Ok(Some(record)) if matches!(record.asm, Asm::ient { .. })))
+            .unwrap_or(decoded.len());
+        let tags = MemTags::collect(
+            decoded[start..end]
+                .iter()
+                .filter_map(|(_, record)| record.as_ref().ok()?.as_ref()),
+        );
+        for (off, record) in &decoded[start..end] {
+            print_record(bytes, *off, record, &tags, endian);
+        }
+        start = end;
+    }
+    decoded
+        .last()
+        .map_or(0, |(off, _)| off + BINASM_RECORD_LENGTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::*;
+    use super::*;
+    use asmcode::*;
+    use register::*;
+
+    // A leaf procedure that returns the sum of its arguments
+    fn add(endian: Endian) -> Binasm {
+        Binasm::new(endian)
+            .text()
+            .globl(1)
+            .ent(1)
+            .label(1)
+            .frame(0, xr29, xr31)
+            .livereg(0x2000_0000, 0)
+            .ins(zjr, r(xr31))
+            .ins(zaddu, rrr(xr2, xr4, xr5))
+            .end(1)
+    }
+
+    fn lines(records: &[Record]) -> Vec<String> {
+        records
+            .iter()
+            .map(|record| record.asm.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_each_record() {
+        assert_eq!(
+            lines(&add(Endian::Big).records()),
+            [
+                "\t.text\t",
+                "\t.globl\tSYM_1",
+                "\t.ent\tSYM_1 0",
+                "SYM_1:",
+                "\t.frame\t$sp, 0, $31",
+                "\t.livereg\t0x20000000,0x00000000",
+                "\tjr\t$31",
+                "\taddu\t$2, $4, $5",
+                "\t.end\tSYM_1",
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_byte_order() {
+        for endian in [Endian::Big, Endian::Little] {
+            assert_eq!(Endian::detect(add(endian).bytes()), endian);
+        }
+        assert_eq!(
+            lines(&add(Endian::Big).records()),
+            lines(&add(Endian::Little).records())
+        );
+    }
+
+    // Operands of one instruction record given as its four words
+    fn operands(words: [u32; 4], endian: Endian) -> ArgFormat {
+        let bytes: Vec<u8> = words
+            .iter()
+            .flat_map(|word| match endian {
+                Endian::Big => word.to_be_bytes(),
+                Endian::Little => word.to_le_bytes(),
+            })
+            .collect();
+        process_args(&bytes, &mut 0, endian).unwrap()
+    }
+
+    #[test]
+    fn decodes_three_and_four_register_operands() {
+        // addu $2, $4, $5: reg1 2, reg2 4, format frrr (3), reg3 5. With reg3 read from bit 14 it
+        // overlapped the format field and came out as $24 to $31 for every frrr record.
+        for (word1, word2, endian) in [
+            (0x0017_0004, 0x0410_C280, Endian::Big),
+            (0x0080_5C00, 0x0014_C202, Endian::Little),
+        ] {
+            assert!(matches!(
+                operands([0, word1, word2, 0], endian),
+                ArgFormat::frrr {
+                    reg1: xr2,
+                    reg2: xr4,
+                    reg3: xr5
+                }
+            ));
+        }
+        // madd.d $f0, $f2, $f4, $f6: format frrrr (15), reg3 36 and reg4 38
+        assert!(matches!(
+            operands([0, 0x0017_02F2, 0x408B_D226, 0], Endian::Big),
+            ArgFormat::frrrr {
+                reg1: xfr0,
+                reg2: xfr2,
+                reg3: xfr4,
+                reg4: xfr6
+            }
+        ));
+    }
+
+    #[test]
+    fn prints_symbolic_words_with_their_addend() {
+        let word = |symno, expression, repeat| Asm::iword {
+            symno,
+            expression,
+            repeat,
+        };
+        assert_eq!(word(3, 8, 1).to_string(), "\t.word\tSYM_3+8");
+        assert_eq!(word(3, -4, 2).to_string(), "\t.word\tSYM_3-4 : 2");
+        assert_eq!(word(-2, 0, 1).to_string(), "\t.word\t$2");
+        assert_eq!(word(0, 5, 1).to_string(), "\t.word\t5 : 1");
+        let gpword = Asm::igpword {
+            symno: -7,
+            expression: 4,
+            repeat: 1,
+        };
+        assert_eq!(gpword.to_string(), "\t.gpword\t$7+4");
+        assert_eq!(
+            gpword.sym_operand(false).unwrap().to_string(),
+            "%gp_rel($7+4)"
+        );
+        let cpload = Asm::icpload {
+            symno: 0,
+            args: r(xr25),
+        };
+        assert_eq!(cpload.to_string(), "\t.cpload\t$25");
+    }
+
+    #[test]
+    fn reports_undecodable_records() {
+        let mut bytes = add(Endian::Big).bytes().to_vec();
+        // Opcode field of the addu, past the last asmcode
+        bytes[0x76] |= 0x03;
+        bytes[0x77] |= 0xFE;
+        // A record cut short
+        bytes.truncate(bytes.len() - 4);
+        let (records, errors) = decode_records(&bytes);
+        assert_eq!(
+            errors,
+            vec![
+                (0x70, DecodeError::Opcode(0x1FF)),
+                (0x80, DecodeError::Truncated)
+            ]
+        );
+        assert_eq!(records.len(), 7);
+    }
 }
@@ -0,0 +1,561 @@
+// Per-opcode semantics for asmcode: the operand formats each opcode takes, which operands it reads
+// and writes, how it transfers control, and which ISA level and FPU precision it belongs to.
+
+use super::*;
+
+// Operand layout of an opcode. Determines the formats it may be encoded with and which registers it
+// reads and writes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    // rd, rs, rt|immed; the two-operand forms read and write the first register
+    Alu,
+    // rd, rs; the one-operand form reads and writes the register
+    Unary,
+    // reg, address; writes reg
+    Load,
+    // reg, address; reads reg and writes nothing but memory
+    Store,
+    // reg, address; merges into reg (lwl, lwr, ldl, ldr)
+    LoadMerge,
+    // reg, address; reads reg and writes the success flag back to it (sc, scd)
+    StoreConditional,
+    // reg, immed|address
+    LoadImmediate,
+    // rs, rt|immed, label
+    Branch2,
+    // rs, label
+    Branch1,
+    // label
+    Branch0,
+    // label or address
+    Jump,
+    // rs
+    JumpRegister,
+    // jalr [rd,] rs
+    CallRegister,
+    // rs, rt into hi/lo, or rd, rs, rt for the division macros
+    MulDiv,
+    // rd from hi or lo
+    MoveFromHiLo,
+    // rs into hi or lo
+    MoveToHiLo,
+    // rt, coprocessor reg; writes rt
+    MoveFromCoprocessor,
+    // rt, coprocessor reg; writes the coprocessor register
+    MoveToCoprocessor,
+    // fs, ft; sets the FP condition
+    FpCompare,
+    // rs, rt|immed
+    Trap,
+    // fd, fr, fs, ft
+    FpMultiplyAdd,
+    // rd, rs, rt|cc; rd is only written when the condition holds
+    ConditionalMove,
+    // Anything without register operands, or whose operands are not modelled
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Other,
+    Load,
+    Store,
+    // Conditional branch
+    Branch,
+    // Unconditional branch or jump to a label: b, j
+    Jump,
+    // Jump to a register: jr
+    JumpRegister,
+    // Any transfer that links: jal, jalr, bal, bgezal, bltzal and their likely forms
+    Call,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Isa {
+    Mips1,
+    Mips2,
+    Mips3,
+    Mips4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    Single,
+    Double,
+    Extended,
+    Word,
+    Long,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct OpInfo {
+    pub shape: Shape,
+    pub kind: Kind,
+    // Empty if the formats are not known
+    pub formats: &'static [format],
+    pub likely: bool,
+    // Pseudo-instruction as1 expands into one or more machine instructions
+    pub is_macro: bool,
+    // Placeholder slot in the opcode table with no known instruction
+    pub unknown: bool,
+    pub isa: Isa,
+    pub precision: Option<Precision>,
+}
+
+fn shape(op: asmcode) -> Shape {
+    use asmcode::*;
+    match op {
+        zadd | zaddu | zand | zor | zxor | znor | zsub | zsubu | zsll | zsra | zsrl | zsllv
+        | zsrlv | zsrav | zslt | zsltu | zaddi | zaddiu | zslti | zsltiu | zandi | zori | zxori
+        | zdaddi | zdaddiu | zdadd | zdaddu | zdsub | zdsubu | zdsll | zdsrl | zdsra | zdsllv
+        | zdsrlv | zdsrav | zdsll32 | zdsrl32 | zdsra32 | zmul | zmulo | zmulou | zrem | zremu
+        | zrol | zror | zseq | zsge | zsgeu | zsgt | zsgtu | zsle | zsleu | zsne | zaddou
+        | zsubou | zdmul | zdmulo | zdmulou | zdrem | zdremu | zdrol | zdror | zdaddou
+        | zdsubou | fadd_s | fadd_d | fadd_e | fsub_s | fsub_d | fsub_e | fmul_s | fmul_d
+        | fmul_e | fdiv_s | fdiv_d | fdiv_e => Shape::Alu,
+
+        zneg | znegu | znot | zmove | zabs | zdabs | zdneg | zdnegu | fsqrt_s | fsqrt_d
+        | fsqrt_e | fmov_s | fmov_d | fmov_e | fabs_s | fabs_d | fabs_e | fneg_s | fneg_d
+        | fneg_e | fcvt_s_d | fcvt_s_e | fcvt_s_w | fcvt_d_s | fcvt_d_e | fcvt_d_w | fcvt_e_s
+        | fcvt_e_d | fcvt_e_w | fcvt_w_s | fcvt_w_d | fcvt_w_e | fcvt_l_s | fcvt_l_d | fcvt_l_e
+        | fcvt_l_w | fcvt_s_l | fcvt_d_l | fcvt_e_l | fcvt_w_l | ztrunc_w_s | ztrunc_w_d
+        | ztrunc_w_e | zround_w_s | zround_w_d | zround_w_e | ztruncu_w_s | ztruncu_w_d
+        | ztruncu_w_e | zroundu_w_s | zroundu_w_d | zroundu_w_e | zceil_w_s | zceil_w_d
+        | zceil_w_e | zceilu_w_s | zceilu_w_d | zceilu_w_e | zfloor_w_s | zfloor_w_d
+        | zfloor_w_e | zflooru_w_s | zflooru_w_d | zflooru_w_e | ztrunc_l_s | zround_l_s
+        | zceil_l_s | zfloor_l_s | ztrunc_l_d | zround_l_d | zceil_l_d | zfloor_l_d
+        | ztrunc_l_e | zround_l_e | zceil_l_e | zfloor_l_e | frecip_s | frecip_d | frsqrt_s
+        | frsqrt_d => Shape::Unary,
+
+        zlb | zlbu | zlh | zlhu | zlw | zld | zlwu | zll | zlld | zlwc1 | zlwc2 | zlwc3
+        | zlwxc1 | zldxc1 | zldc1 | zldc2 | fl_s | fl_d | fl_e | zulw | zulh | zulhu | zuld
+        | zulwu | zlsc1 => Shape::Load,
+        zlwl | zlwr | zldl | zldr => Shape::LoadMerge,
+        zsb | zsh | zsw | zswl | zswr | zsd | zsdl | zsdr | zswc1 | zswc2 | zswc3 | zswxc1
+        | zsdxc1 | zsdc1 | zsdc2 | fs_s | fs_d | fs_e | zusw | zush | zusd | zssc1 => Shape::Store,
+        zsc | zscd => Shape::StoreConditional,
+        zli | zla | zlui | zdli | zdla | fli_s | fli_d | fli_e => Shape::LoadImmediate,
+
+        zbeq | zbne | zbge | zbgeu | zbgt | zbgtu | zble | zbleu | zblt | zbltu | zbeql | zbnel
+        | zbgel | zbgeul | zbgtl | zbgtul | zblel | zbleul | zbltl | zbltul => Shape::Branch2,
+        zbgez | zbgtz | zblez | zbltz | zbeqz | zbnez | zbeqzl | zbnezl | zbgezl | zbgtzl
+        | zblezl | zbltzl | zbgezal | zbltzal | zbgezall | zbltzall => Shape::Branch1,
+        zb | zbal | zbc0f | zbc0t | zbc1f | zbc1t | zbc2f | zbc2t | zbc3f | zbc3t | zbc0fl
+        | zbc0tl | zbc1fl | zbc1tl | zbc2fl | zbc2tl => Shape::Branch0,
+        zj | zjal => Shape::Jump,
+        zjr => Shape::JumpRegister,
+        zjalr => Shape::CallRegister,
+
+        zmult | zmultu | zdmult | zdmultu | zdiv | zdivu | zddiv | zddivu => Shape::MulDiv,
+        zmfhi | zmflo => Shape::MoveFromHiLo,
+        zmthi | zmtlo => Shape::MoveToHiLo,
+        zmfc0 | zmfc1 | zmfc2 | zmfc3 | zcfc3 | zcfc0 | zcfc1 | zcfc2 | zdmfc0 | zdmfc1
+        | zdmfc2 | zmfc1_d => Shape::MoveFromCoprocessor,
+        zmtc0 | zmtc1 | zmtc2 | zmtc3 | zctc3 | zctc0 | zctc1 | zctc2 | zdmtc0 | zdmtc1
+        | zdmtc2 | zmtc1_d => Shape::MoveToCoprocessor,
+
+        fc_f_s | fc_f_d | fc_f_e | fc_un_s | fc_un_d | fc_un_e | fc_eq_s | fc_eq_d | fc_eq_e
+        | fc_ueq_s | fc_ueq_d | fc_ueq_e | fc_olt_s | fc_olt_d | fc_olt_e | fc_ult_s | fc_ult_d
+        | fc_ult_e | fc_ole_s | fc_ole_d | fc_ole_e | fc_ule_s | fc_ule_d | fc_ule_e | fc_sf_s
+        | fc_sf_d | fc_sf_e | fc_ngle_s | fc_ngle_d | fc_ngle_e | fc_seq_s | fc_seq_d
+        | fc_seq_e | fc_ngl_s | fc_ngl_d | fc_ngl_e | fc_lt_s | fc_lt_d | fc_lt_e | fc_nge_s
+        | fc_nge_d | fc_nge_e | fc_le_s | fc_le_d | fc_le_e | fc_ngt_s | fc_ngt_d | fc_ngt_e => {
+            Shape::FpCompare
+        }
+
+        ztlt | ztltu | ztge | ztgeu | zteq | ztne => Shape::Trap,
+        fmadd_s | fmadd_d | fmadd_e | fmsub_s | fmsub_d | fmsub_e | fnmadd_s | fnmadd_d
+        | fnmadd_e | fnmsub_s | fnmsub_d | fnmsub_e => Shape::FpMultiplyAdd,
+        zmovt | zmovf | zmovn | zmovz | fmovt_s | fmovt_d | fmovf_s | fmovf_d | fmovn_s
+        | fmovn_d | fmovz_s | fmovz_d => Shape::ConditionalMove,
+
+        _ => Shape::Other,
+    }
+}
+
+fn formats(shape: Shape) -> &'static [format] {
+    match shape {
+        Shape::Alu => &[format::frrr, format::frri, format::frr, format::fri],
+        Shape::Unary => &[format::frr, format::fr],
+        Shape::Load | Shape::Store | Shape::LoadMerge | Shape::StoreConditional => {
+            &[format::frob, format::fra]
+        }
+        Shape::LoadImmediate => &[format::fri, format::fra, format::frob],
+        Shape::Branch2 => &[format::frrl, format::fril],
+        Shape::Branch1 => &[format::frl],
+        Shape::Branch0 => &[format::fl],
+        Shape::Jump => &[format::fl, format::fa, format::fr],
+        Shape::JumpRegister => &[format::fr],
+        Shape::CallRegister => &[format::fr, format::frr],
+        Shape::MulDiv => &[format::frr, format::frrr, format::frri],
+        Shape::MoveFromHiLo | Shape::MoveToHiLo => &[format::fr],
+        Shape::MoveFromCoprocessor | Shape::MoveToCoprocessor | Shape::FpCompare => &[format::frr],
+        Shape::Trap => &[format::frr, format::fri],
+        Shape::FpMultiplyAdd => &[format::frrrr],
+        Shape::ConditionalMove => &[format::frrr],
+        Shape::Other => &[],
+    }
+}
+
+fn kind(op: asmcode, shape: Shape) -> Kind {
+    use asmcode::*;
+    match op {
+        zjal | zjalr | zbal | zbgezal | zbltzal | zbgezall | zbltzall => Kind::Call,
+        zb | zj => Kind::Jump,
+        zjr => Kind::JumpRegister,
+        _ => match shape {
+            Shape::Load | Shape::LoadMerge => Kind::Load,
+            Shape::Store | Shape::StoreConditional => Kind::Store,
+            Shape::Branch2 | Shape::Branch1 | Shape::Branch0 => Kind::Branch,
+            _ => Kind::Other,
+        },
+    }
+}
+
+fn is_likely(op: asmcode) -> bool {
+    use asmcode::*;
+    matches!(
+        op,
+        zbeql
+            | zbeqzl
+            | zbnel
+            | zbnezl
+            | zblel
+            | zbleul
+            | zblezl
+            | zbgtl
+            | zbgtul
+            | zbgtzl
+            | zbltl
+            | zbltul
+            | zbltzl
+            | zbltzall
+            | zbgel
+            | zbgeul
+            | zbgezl
+            | zbgezall
+            | zbc0fl
+            | zbc0tl
+            | zbc1fl
+            | zbc1tl
+            | zbc2fl
+            | zbc2tl
+    )
+}
+
+// Placeholder slots this tree has no mnemonic for. Without as1's opcode table none of them can be
+// named or shown to be unused, so records using them still decode under the placeholder name.
+fn is_unknown(op: asmcode) -> bool {
+    use asmcode::*;
+    matches!(
+        op,
+        z12 | z31 | z53 | z99 | z110 | z117 | z218 | z248 | z251 | z299 | z300
+    )
+}
+
+fn is_macro(op: asmcode) -> bool {
+    use asmcode::*;
+    matches!(
+        op,
+        zabs | zb
+            | zbeqz
+            | zbnez
+            | zbge
+            | zbgeu
+            | zbgt
+            | zbgtu
+            | zble
+            | zbleu
+            | zblt
+            | zbltu
+            | zbeqzl
+            | zbnezl
+            | zbgel
+            | zbgeul
+            | zbgtl
+            | zbgtul
+            | zblel
+            | zbleul
+            | zbltl
+            | zbltul
+            | zdiv
+            | zdivu
+            | zddiv
+            | zddivu
+            | zla
+            | zli
+            | zdla
+            | zdli
+            | zmove
+            | zmul
+            | zmulo
+            | zmulou
+            | zneg
+            | znegu
+            | znot
+            | zrem
+            | zremu
+            | zrol
+            | zror
+            | zseq
+            | zsge
+            | zsgeu
+            | zsgt
+            | zsgtu
+            | zsle
+            | zsleu
+            | zsne
+            | zulw
+            | zulh
+            | zulhu
+            | zusw
+            | zush
+            | zuld
+            | zusd
+            | zulwu
+            | zdabs
+            | zdneg
+            | zdnegu
+            | zdmul
+            | zdmulo
+            | zdmulou
+            | zdrem
+            | zdremu
+            | zdrol
+            | zdror
+            | zaddou
+            | zsubou
+            | zdaddou
+            | zdsubou
+            | fl_s
+            | fl_d
+            | fl_e
+            | fs_s
+            | fs_d
+            | fs_e
+            | fli_s
+            | fli_d
+            | fli_e
+            | ztrunc_w_s
+            | ztrunc_w_d
+            | ztrunc_w_e
+            | zround_w_s
+            | zround_w_d
+            | zround_w_e
+            | zceil_w_s
+            | zceil_w_d
+            | zceil_w_e
+            | zfloor_w_s
+            | zfloor_w_d
+            | zfloor_w_e
+            | ztruncu_w_s
+            | ztruncu_w_d
+            | ztruncu_w_e
+            | zroundu_w_s
+            | zroundu_w_d
+            | zroundu_w_e
+            | zceilu_w_s
+            | zceilu_w_d
+            | zceilu_w_e
+            | zflooru_w_s
+            | zflooru_w_d
+            | zflooru_w_e
+            | zmtc1_d
+            | zmfc1_d
+    )
+}
+
+fn isa(op: asmcode) -> Isa {
+    use asmcode::*;
+    if is_likely(op) {
+        return Isa::Mips2;
+    }
+    match op {
+        zll | zsc | ztlt | ztltu | ztge | ztgeu | zteq | ztne | zldc1 | zldc2 | zsdc1 | zsdc2
+        | zsync | fsqrt_s | fsqrt_d | fsqrt_e => Isa::Mips2,
+
+        zld | zsd | zldl | zldr | zlld | zlwu | zsdl | zsdr | zscd | zdaddi | zdaddiu | zdadd
+        | zdaddu | zdsub | zdsubu | zdsll | zdsrl | zdsra | zdsllv | zdsrlv | zdsrav | zdsll32
+        | zdsrl32 | zdsra32 | zdmult | zdmultu | zddiv | zddivu | zdmtc1 | zdmfc1 | zdmtc0
+        | zdmfc0 | zdmtc2 | zdmfc2 | zdli | zdla | zeret | ztrunc_l_s | zround_l_s | zceil_l_s
+        | zfloor_l_s | ztrunc_l_d | zround_l_d | zceil_l_d | zfloor_l_d | ztrunc_l_e
+        | zround_l_e | zceil_l_e | zfloor_l_e | fcvt_l_s | fcvt_l_d | fcvt_l_e | fcvt_l_w
+        | fcvt_s_l | fcvt_d_l | fcvt_e_l | fcvt_w_l | zuld | zusd | zdabs | zdneg | zdnegu
+        | zdmul | zdmulo | zdmulou | zdrem | zdremu | zdrol | zdror | zdaddou | zdsubou | zulwu => {
+            Isa::Mips3
+        }
+
+        zmovt | zmovf | zmovn | zmovz | fmadd_s | fmadd_d | fmadd_e | fmsub_s | fmsub_d
+        | fmsub_e | fnmadd_s | fnmadd_d | fnmadd_e | fnmsub_s | fnmsub_d | fnmsub_e | frecip_s
+        | frecip_d | frsqrt_s | frsqrt_d | fmovt_s | fmovt_d | fmovf_s | fmovf_d | fmovn_s
+        | fmovn_d | fmovz_s | fmovz_d | zlwxc1 | zldxc1 | zswxc1 | zsdxc1 | zpfetch | zpref => {
+            Isa::Mips4
+        }
+
+        _ => Isa::Mips1,
+    }
+}
+
+// FPU precision an instruction operates on, from the fmt suffix of its name. Conversions take the
+// precision of their source.
+fn precision(op: asmcode) -> Option<Precision> {
+    use asmcode::*;
+    match op {
+        zlwc1 | zswc1 | zmtc1 | zmfc1 => return Some(Precision::Single),
+        zldc1 | zsdc1 | zmtc1_d | zmfc1_d => return Some(Precision::Double),
+        _ => {}
+    }
+    let name: &'static str = op.into();
+    if !(name.starts_with('f') || name.contains("_w_") || name.contains("_l_")) {
+        return None;
+    }
+    match name.rsplit('_').next() {
+        Some("s") => Some(Precision::Single),
+        Some("d") => Some(Precision::Double),
+        Some("e") => Some(Precision::Extended),
+        Some("w") => Some(Precision::Word),
+        Some("l") => Some(Precision::Long),
+        _ => None,
+    }
+}
+
+pub fn op_info(op: asmcode) -> OpInfo {
+    let shape = shape(op);
+    OpInfo {
+        shape,
+        kind: kind(op, shape),
+        formats: formats(shape),
+        likely: is_likely(op),
+        is_macro: is_macro(op),
+        unknown: is_unknown(op),
+        isa: isa(op),
+        precision: precision(op),
+    }
+}
+
+// Registers an instruction writes and reads, including implicit ones: the link register for calls
+// and the FP condition for compares and FP branches. hi and lo are not modelled.
+pub fn defs_uses(op: asmcode, args: &ArgFormat) -> (Vec<register>, Vec<register>) {
+    let mut defs = Vec::new();
+    let mut uses = Vec::new();
+    let info = op_info(op);
+
+    match (info.shape, args) {
+        (Shape::Alu | Shape::ConditionalMove, ArgFormat::frrr { reg1, reg2, reg3 }) => {
+            if info.shape == Shape::ConditionalMove {
+                uses.push(*reg1);
+            }
+            defs.push(*reg1);
+            uses.extend([*reg2, *reg3]);
+        }
+        (Shape::Alu, ArgFormat::frri { reg1, reg2, .. }) => {
+            defs.push(*reg1);
+            uses.push(*reg2);
+        }
+        (Shape::Alu, ArgFormat::frr { reg1, reg2 }) => {
+            defs.push(*reg1);
+            uses.extend([*reg1, *reg2]);
+        }
+        (Shape::Alu | Shape::Unary, ArgFormat::fri { reg, .. } | ArgFormat::fr { reg }) => {
+            defs.push(*reg);
+            uses.push(*reg);
+        }
+        (Shape::Unary | Shape::MoveFromCoprocessor, ArgFormat::frr { reg1, reg2 }) => {
+            defs.push(*reg1);
+            uses.push(*reg2);
+        }
+        (Shape::MoveToCoprocessor, ArgFormat::frr { reg1, reg2 }) => {
+            defs.push(*reg2);
+            uses.push(*reg1);
+        }
+        (
+            Shape::Load | Shape::LoadMerge | Shape::Store | Shape::StoreConditional,
+            ArgFormat::frob { reg, base, .. },
+        ) => {
+            match info.shape {
+                Shape::Load => defs.push(*reg),
+                Shape::Store => uses.push(*reg),
+                _ => {
+                    defs.push(*reg);
+                    uses.push(*reg);
+                }
+            }
+            uses.push(*base);
+        }
+        (
+            Shape::Load | Shape::LoadMerge | Shape::Store | Shape::StoreConditional,
+            ArgFormat::fra { reg, .. },
+        ) => match info.shape {
+            Shape::Load => defs.push(*reg),
+            Shape::Store => uses.push(*reg),
+            _ => {
+                defs.push(*reg);
+                uses.push(*reg);
+            }
+        },
+        (Shape::LoadImmediate, ArgFormat::fri { reg, .. } | ArgFormat::fra { reg, .. }) => {
+            defs.push(*reg)
+        }
+        (Shape::LoadImmediate, ArgFormat::frob { reg, base, .. }) => {
+            defs.push(*reg);
+            uses.push(*base);
+        }
+        (Shape::Branch2, ArgFormat::frrl { reg1, reg2, .. }) => uses.extend([*reg1, *reg2]),
+        (Shape::Branch2 | Shape::Branch1, ArgFormat::fril { reg, .. })
+        | (Shape::Branch1, ArgFormat::frl { reg, .. }) => uses.push(*reg),
+        (Shape::Jump | Shape::JumpRegister | Shape::CallRegister, ArgFormat::fr { reg }) => {
+            uses.push(*reg)
+        }
+        (Shape::Jump, ArgFormat::fa { base, .. }) => uses.push(*base),
+        (Shape::CallRegister, ArgFormat::frr { reg1, reg2 }) => {
+            defs.push(*reg1);
+            uses.push(*reg2);
+        }
+        (Shape::MulDiv, ArgFormat::frr { reg1, reg2 }) => uses.extend([*reg1, *reg2]),
+        (Shape::MulDiv, ArgFormat::frrr { reg1, reg2, reg3 }) => {
+            defs.push(*reg1);
+            uses.extend([*reg2, *reg3]);
+        }
+        (Shape::MulDiv, ArgFormat::frri { reg1, reg2, .. }) => {
+            defs.push(*reg1);
+            uses.push(*reg2);
+        }
+        (Shape::MoveFromHiLo, ArgFormat::fr { reg }) => defs.push(*reg),
+        (Shape::MoveToHiLo, ArgFormat::fr { reg }) => uses.push(*reg),
+        (Shape::FpCompare, ArgFormat::frr { reg1, reg2 }) => {
+            defs.push(register::xfcc0);
+            uses.extend([*reg1, *reg2]);
+        }
+        (Shape::Trap, ArgFormat::frr { reg1, reg2 }) => uses.extend([*reg1, *reg2]),
+        (Shape::Trap, ArgFormat::fri { reg, .. }) => uses.push(*reg),
+        (
+            Shape::FpMultiplyAdd,
+            ArgFormat::frrrr {
+                reg1,
+                reg2,
+                reg3,
+                reg4,
+            },
+        ) => {
+            defs.push(*reg1);
+            uses.extend([*reg2, *reg3, *reg4]);
+        }
+        _ => {}
+    }
+
+    use asmcode::*;
+    match op {
+        zbc1f | zbc1t | zbc1fl | zbc1tl | zmovt | zmovf | fmovt_s | fmovt_d | fmovf_s | fmovf_d => {
+            uses.push(register::xfcc0)
+        }
+        _ => {}
+    }
+    if info.kind == Kind::Call && !matches!(args, ArgFormat::frr { .. }) {
+        defs.push(register::xr31);
+    }
+
+    defs.retain(|r| *r != register::xnoreg);
+    uses.retain(|r| *r != register::xnoreg);
+    (defs, uses)
+}
@@ -0,0 +1,187 @@
+// Cross-checks each procedure's .frame/.mask/.fmask against the procedure descriptors in the
+// symbol table.
+
+use super::*;
+use crate::st::SymbolTable;
+
+#[derive(Default)]
+struct ProcInfo {
+    symno: i32,
+    offset: usize,
+    frame: Option<(usize, i32, register, register)>,
+    mask: Option<(usize, u32, i32)>,
+    fmask: Option<(usize, u32, i32)>,
+}
+
+fn check_procedure(st: &SymbolTable, proc: &ProcInfo) -> usize {
+    let name = st.dense_name(proc.symno as u32).unwrap_or("?");
+    let Some(pd) = st.procedure(proc.symno as u32) else {
+        println!(
+            "0x{:06X}: .ent SYM_{} ({name}) has no procedure descriptor",
+            proc.offset, proc.symno
+        );
+        return 1;
+    };
+
+    let mut mismatches = 0;
+    let mut report = |off: usize, what: &str, binasm: String, pdr: String| {
+        println!("0x{off:06X}: {name}: {what} is {binasm} in binasm but {pdr} in PDR");
+        mismatches += 1;
+    };
+
+    if let Some((off, frameoffset, framereg, pcreg)) = proc.frame {
+        if frameoffset != pd.frameoffset {
+            report(
+                off,
+                "frame offset",
+                frameoffset.to_string(),
+                pd.frameoffset.to_string(),
+            );
+        }
+        if framereg as i16 != pd.framereg {
+            report(
+                off,
+                "frame register",
+                framereg.to_string(),
+                format!("${}", pd.framereg),
+            );
+        }
+        if pcreg as i16 != pd.pcreg {
+            report(
+                off,
+                "pc register",
+                pcreg.to_string(),
+                format!("${}", pd.pcreg),
+            );
+        }
+    }
+    let (off, regmask, regoffset) = proc.mask.unwrap_or((proc.offset, 0, 0));
+    if regmask != pd.regmask {
+        report(
+            off,
+            "register mask",
+            format!("0x{regmask:08X}"),
+            format!("0x{:08X}", pd.regmask),
+        );
+    }
+    if regoffset != pd.regoffset {
+        report(
+            off,
+            "register offset",
+            regoffset.to_string(),
+            pd.regoffset.to_string(),
+        );
+    }
+    let (off, fregmask, fregoffset) = proc.fmask.unwrap_or((proc.offset, 0, 0));
+    if fregmask != pd.fregmask {
+        report(
+            off,
+            "fp register mask",
+            format!("0x{fregmask:08X}"),
+            format!("0x{:08X}", pd.fregmask),
+        );
+    }
+    if fregoffset != pd.fregoffset {
+        report(
+            off,
+            "fp register offset",
+            fregoffset.to_string(),
+            pd.fregoffset.to_string(),
+        );
+    }
+
+    mismatches
+}
+
+fn missing_end(proc: &ProcInfo) -> usize {
+    println!("0x{:06X}: .ent SYM_{} has no .end", proc.offset, proc.symno);
+    1
+}
+
+// Pairs each .ent with its procedure descriptor and reports any differences in frame layout.
+// Returns the number of mismatches found.
+pub fn check_pdrs(bytes: &[u8], st: &SymbolTable) -> usize {
+    let mut mismatches = 0;
+    let mut current: Option<ProcInfo> = None;
+
+    for Record {
+        offset: off, asm, ..
+    } in read_records(bytes)
+    {
+        match asm {
+            Asm::ient { symno, lexlev: _ } => {
+                if let Some(proc) = current.take() {
+                    mismatches += missing_end(&proc);
+                    mismatches += check_procedure(st, &proc);
+                }
+                current = Some(ProcInfo {
+                    symno,
+                    offset: off,
+                    ..Default::default()
+                });
+            }
+            Asm::iframe {
+                frameoffset,
+                framereg,
+                pcreg,
+            } => {
+                if let Some(proc) = current.as_mut() {
+                    proc.frame = Some((off, frameoffset, framereg, pcreg));
+                }
+            }
+            Asm::imask { regmask, regoffset } => {
+                if let Some(proc) = current.as_mut() {
+                    proc.mask = Some((off, regmask, regoffset));
+                }
+            }
+            Asm::ifmask { regmask, regoffset } => {
+                if let Some(proc) = current.as_mut() {
+                    proc.fmask = Some((off, regmask, regoffset));
+                }
+            }
+            Asm::iend { symno } => match current.take() {
+                Some(proc) => {
+                    if proc.symno != symno {
+                        println!(
+                            "0x{off:06X}: .end SYM_{symno} closes .ent SYM_{}",
+                            proc.symno
+                        );
+                        mismatches += 1;
+                    }
+                    mismatches += check_procedure(st, &proc);
+                }
+                None => {
+                    println!("0x{off:06X}: .end SYM_{symno} has no .ent");
+                    mismatches += 1;
+                }
+            },
+            _ => {}
+        }
+    }
+    if let Some(proc) = current.take() {
+        mismatches += missing_end(&proc);
+        mismatches += check_procedure(st, &proc);
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::program;
+    use super::*;
+    use crate::st::fixture::symbol_table;
+
+    #[test]
+    fn matches_procedure_descriptors() {
+        let st = SymbolTable::parse(&symbol_table(true, 0)).unwrap();
+        assert_eq!(check_pdrs(program(Endian::Big).bytes(), &st), 0);
+
+        // helper's frame is 0 bytes in its descriptor
+        let bytes = program(Endian::Big)
+            .ent(2)
+            .frame(8, register::xr29, register::xr31)
+            .end(2);
+        assert_eq!(check_pdrs(bytes.bytes(), &st), 1);
+    }
+}
@@ -0,0 +1,44 @@
+use super::*;
+
+// A procedure's records, from its .ent to its .end inclusive
+pub struct Procedure {
+    pub symno: i32,
+    pub records: Vec<Record>,
+}
+
+// Collects the records between each .ent and the matching .end. Records outside any procedure are
+// dropped.
+pub fn procedures(records: &[Record]) -> Vec<Procedure> {
+    let mut procs = Vec::new();
+    let mut current: Option<Procedure> = None;
+
+    for record in records {
+        match record.asm {
+            Asm::ient { symno, lexlev: _ } => {
+                if let Some(proc) = current.take() {
+                    procs.push(proc);
+                }
+                current = Some(Procedure {
+                    symno,
+                    records: vec![record.clone()],
+                });
+            }
+            Asm::iend { symno: _ } => {
+                if let Some(mut proc) = current.take() {
+                    proc.records.push(record.clone());
+                    procs.push(proc);
+                }
+            }
+            _ => {
+                if let Some(proc) = current.as_mut() {
+                    proc.records.push(record.clone());
+                }
+            }
+        }
+    }
+    if let Some(proc) = current.take() {
+        procs.push(proc);
+    }
+
+    procs
+}
@@ -0,0 +1,316 @@
+// Cross-checks each procedure's .frame/.mask/.fmask against the stack adjustment and register saves
+// in its prologue.
+
+use super::procedure::{procedures, Procedure};
+use super::*;
+use crate::st::SymbolTable;
+
+// What the prologue actually does
+#[derive(Default)]
+struct Prologue {
+    frame_size: i32,
+    regmask: u32,
+    // Stack offset of the save of the highest-numbered register, if any
+    highest_save: Option<i32>,
+    fregmask: u32,
+    highest_fsave: Option<i32>,
+}
+
+fn is_callee_saved(reg: usize) -> bool {
+    (16..=23).contains(&reg) || reg == 30 || reg == 31
+}
+
+// Index of an FP register among $f0-$f31 if it is one the callee must preserve
+fn callee_saved_fp(reg: register) -> Option<usize> {
+    let freg = (reg as usize).checked_sub(register::xfr0 as usize)?;
+    (20..=31).contains(&freg).then_some(freg)
+}
+
+// Constant an instruction adds to $sp in place. The assembler takes the add and subtract macros
+// with an immediate in either operand form, and IDO's compiler writes `subu $sp, N`.
+fn sp_adjustment(op: asmcode, args: &ArgFormat) -> Option<i32> {
+    let immediate = match args {
+        ArgFormat::fri {
+            reg: register::xr29,
+            immediate,
+            ..
+        }
+        | ArgFormat::frri {
+            reg1: register::xr29,
+            reg2: register::xr29,
+            immediate,
+            ..
+        } => *immediate,
+        _ => return None,
+    };
+    match op {
+        asmcode::zaddiu | asmcode::zaddi | asmcode::zdaddiu | asmcode::zaddu | asmcode::zdaddu => {
+            Some(immediate)
+        }
+        asmcode::zsubu | asmcode::zdsubu => Some(immediate.wrapping_neg()),
+        _ => None,
+    }
+}
+
+// Interprets the procedure's instructions up to the first label or control transfer: the stack
+// pointer adjustment and the stores of callee-saved registers relative to $sp.
+fn read_prologue(proc: &Procedure) -> Prologue {
+    let mut prologue = Prologue::default();
+    // Value last loaded into each register with li, for frames too large for an addiu
+    let mut constants: Vec<(register, i32)> = Vec::new();
+    let mut saves: Vec<(usize, i32)> = Vec::new();
+    let mut fsaves: Vec<(usize, i32)> = Vec::new();
+    let mut has_code = false;
+
+    for record in &proc.records {
+        let (op, args) = match &record.asm {
+            Asm::iocode { op, args } => (*op, args),
+            Asm::ilabel { .. } | Asm::ilab { .. } if has_code => break,
+            _ => continue,
+        };
+        has_code = true;
+        if transfer(op).is_some() {
+            break;
+        }
+
+        if let Some(adjustment) = sp_adjustment(op, args) {
+            if adjustment < 0 {
+                prologue.frame_size = -adjustment;
+            }
+            continue;
+        }
+        match (op, args) {
+            (asmcode::zli, ArgFormat::fri { reg, immediate, .. }) => {
+                constants.push((*reg, *immediate))
+            }
+            (
+                asmcode::zsubu | asmcode::zdsubu,
+                ArgFormat::frrr {
+                    reg1: register::xr29,
+                    reg2: register::xr29,
+                    reg3,
+                },
+            ) => {
+                if let Some((_, size)) = constants.iter().rev().find(|(r, _)| r == reg3) {
+                    prologue.frame_size = *size;
+                }
+            }
+            (
+                asmcode::zsw | asmcode::zsd,
+                ArgFormat::frob {
+                    reg,
+                    offset,
+                    base: register::xr29,
+                    ..
+                },
+            ) if is_callee_saved(*reg as usize) => saves.push((*reg as usize, *offset as i32)),
+            (
+                asmcode::zswc1 | asmcode::fs_s,
+                ArgFormat::frob {
+                    reg,
+                    offset,
+                    base: register::xr29,
+                    ..
+                },
+            ) => {
+                if let Some(freg) = callee_saved_fp(*reg) {
+                    fsaves.push((freg, *offset as i32));
+                }
+            }
+            // A double save covers both halves of an even/odd register pair
+            (
+                asmcode::zsdc1 | asmcode::fs_d,
+                ArgFormat::frob {
+                    reg,
+                    offset,
+                    base: register::xr29,
+                    ..
+                },
+            ) => {
+                if let Some(freg) = callee_saved_fp(*reg) {
+                    fsaves.push((freg, *offset as i32));
+                    fsaves.push((freg | 1, *offset as i32));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (reg, _) in &saves {
+        prologue.regmask |= 1 << reg;
+    }
+    prologue.highest_save = saves
+        .iter()
+        .max_by_key(|(reg, _)| *reg)
+        .map(|(_, off)| *off);
+    for (reg, _) in &fsaves {
+        prologue.fregmask |= 1 << reg;
+    }
+    prologue.highest_fsave = fsaves
+        .iter()
+        .max_by_key(|(reg, _)| *reg)
+        .map(|(_, off)| *off);
+
+    prologue
+}
+
+fn check_procedure(proc: &Procedure, name: &str) -> usize {
+    let mut frame = None;
+    let mut mask = None;
+    let mut fmask = None;
+    for record in &proc.records {
+        match record.asm {
+            Asm::iframe { frameoffset, .. } => frame = Some((record.offset, frameoffset)),
+            Asm::imask { regmask, regoffset } => mask = Some((record.offset, regmask, regoffset)),
+            Asm::ifmask { regmask, regoffset } => fmask = Some((record.offset, regmask, regoffset)),
+            _ => {}
+        }
+    }
+
+    let prologue = read_prologue(proc);
+    let proc_offset = proc.records[0].offset;
+    let mut mismatches = 0;
+    let mut report = |off: usize, what: &str, directive: String, actual: String| {
+        println!(
+            "0x{off:06X}: {name}: {what} is {directive} in directives but {actual} in prologue"
+        );
+        mismatches += 1;
+    };
+
+    let (off, frameoffset) = frame.unwrap_or((proc_offset, 0));
+    if frameoffset != prologue.frame_size {
+        report(
+            off,
+            "frame size",
+            frameoffset.to_string(),
+            prologue.frame_size.to_string(),
+        );
+    }
+
+    // Save offsets are given relative to the top of the frame
+    let (off, regmask, regoffset) = mask.unwrap_or((proc_offset, 0, 0));
+    if regmask != prologue.regmask {
+        report(
+            off,
+            "register mask",
+            format!("0x{regmask:08X}"),
+            format!("0x{:08X}", prologue.regmask),
+        );
+    }
+    if let Some(save) = prologue.highest_save {
+        if regoffset != save - frameoffset {
+            report(
+                off,
+                "register offset",
+                regoffset.to_string(),
+                (save - frameoffset).to_string(),
+            );
+        }
+    }
+    let (off, fregmask, fregoffset) = fmask.unwrap_or((proc_offset, 0, 0));
+    if fregmask != prologue.fregmask {
+        report(
+            off,
+            "fp register mask",
+            format!("0x{fregmask:08X}"),
+            format!("0x{:08X}", prologue.fregmask),
+        );
+    }
+    if let Some(save) = prologue.highest_fsave {
+        if fregoffset != save - frameoffset {
+            report(
+                off,
+                "fp register offset",
+                fregoffset.to_string(),
+                (save - frameoffset).to_string(),
+            );
+        }
+    }
+
+    mismatches
+}
+
+// Compares each procedure's frame directives with what its prologue does. Returns the number of
+// mismatches found.
+pub fn check_prologues(bytes: &[u8], st: Option<&SymbolTable>) -> usize {
+    procedures(&read_records(bytes))
+        .iter()
+        .map(|proc| {
+            let name = st
+                .and_then(|st| st.dense_name(proc.symno as u32))
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("SYM_{}", proc.symno));
+            check_procedure(proc, &name)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::*;
+    use super::*;
+    use asmcode::*;
+    use register::*;
+
+    #[test]
+    fn matches_frame_directives() {
+        assert_eq!(check_prologues(program(Endian::Big).bytes(), None), 0);
+    }
+
+    #[test]
+    fn reads_saves_and_frame_size() {
+        let file = Binasm::new(Endian::Big)
+            .ent(1)
+            .label(1)
+            .frame(40, xr29, xr31)
+            .mask(0x8001_0000, -8)
+            .fmask(0x0030_0000, -16)
+            .ins(zaddiu, rri(xr29, xr29, -40))
+            .ins(zsw, ob(xr31, 32, xr29))
+            .ins(zsw, ob(xr16, 28, xr29))
+            .ins(zsdc1, ob(xfr20, 24, xr29))
+            .ins(zjal, a(2))
+            .end(1);
+        let procs = procedures(&file.records());
+        let prologue = read_prologue(&procs[0]);
+        assert_eq!(prologue.frame_size, 40);
+        assert_eq!(prologue.regmask, 0x8001_0000);
+        assert_eq!(prologue.highest_save, Some(32));
+        assert_eq!(prologue.fregmask, 0x0030_0000);
+        assert_eq!(prologue.highest_fsave, Some(24));
+        assert_eq!(check_prologues(file.bytes(), None), 0);
+    }
+
+    #[test]
+    fn reads_subu_and_addu_frames() {
+        // As IDO cc -S writes it: subu $sp, 32 / sw $31, 28($sp) / sw $16, 24($sp)
+        let ido = Binasm::new(Endian::Big)
+            .ent(1)
+            .label(1)
+            .frame(32, xr29, xr31)
+            .mask(0x8001_0000, -4)
+            .ins(zsubu, ri(xr29, 32))
+            .ins(zsw, ob(xr31, 28, xr29))
+            .ins(zsw, ob(xr16, 24, xr29))
+            .ins(zjal, a(2))
+            .end(1);
+        let procs = procedures(&ido.records());
+        assert_eq!(read_prologue(&procs[0]).frame_size, 32);
+        assert_eq!(check_prologues(ido.bytes(), None), 0);
+
+        for (op, args) in [
+            (zsubu, rri(xr29, xr29, 16)),
+            (zdsubu, ri(xr29, 16)),
+            (zaddu, ri(xr29, -16)),
+            (zdaddu, rri(xr29, xr29, -16)),
+        ] {
+            let file = Binasm::new(Endian::Big)
+                .ent(1)
+                .label(1)
+                .ins(op, args)
+                .end(1);
+            let procs = procedures(&file.records());
+            assert_eq!(read_prologue(&procs[0]).frame_size, 16, "{op}");
+        }
+    }
+}
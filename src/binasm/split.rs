@@ -0,0 +1,175 @@
+// Splits a binasm file into one .s file per procedure and one per run of data, in the layout
+// decompilation projects use for unmatched functions.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::*;
+use crate::st::SymbolTable;
+
+enum Section {
+    Text,
+    Data(&'static str),
+}
+
+struct Chunk {
+    name: String,
+    records: Vec<Asm>,
+}
+
+fn symbol_name(symno: i32, st: Option<&SymbolTable>) -> String {
+    st.and_then(|st| st.dense_name(symno as u32))
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("SYM_{symno}"))
+}
+
+// File name for a chunk. Symbol names may contain anything, so only characters that are safe in
+// a file name are kept and the name cannot start with a dot, which keeps every file inside the
+// output directory.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .enumerate()
+        .map(|(k, c)| match c {
+            '.' if k == 0 => '_',
+            c if c.is_ascii_alphanumeric() || "_.$-".contains(c) => c,
+            _ => '_',
+        })
+        .collect();
+    if stem.is_empty() {
+        "_".to_string()
+    } else {
+        stem
+    }
+}
+
+// Cuts the record stream into procedures and data runs. Text-section records between procedures
+// (.globl, .loc, .align and the like) are kept with the procedure that follows them.
+fn split_records(records: Vec<Record>, st: Option<&SymbolTable>) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut section = Section::Text;
+    let mut pending: Vec<Asm> = Vec::new();
+    let mut in_procedure = false;
+    // Runs are numbered separately for each section
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+
+    let mut flush = |section: &Section, pending: &mut Vec<Asm>, chunks: &mut Vec<Chunk>| {
+        if pending.iter().all(|asm| matches!(asm, Asm::itext)) {
+            pending.clear();
+            return;
+        }
+        let kind = match section {
+            Section::Text => "text",
+            Section::Data(kind) => kind,
+        };
+        let count = counts.entry(kind).or_insert(0);
+        let name = format!("{kind}_{count}");
+        *count += 1;
+        chunks.push(Chunk {
+            name,
+            records: std::mem::take(pending),
+        });
+    };
+
+    for Record { asm, .. } in records {
+        let switch_to = match asm {
+            Asm::itext => Some(Section::Text),
+            Asm::idata { .. } => Some(Section::Data("data")),
+            Asm::isdata { .. } => Some(Section::Data("sdata")),
+            Asm::irdata { .. } => Some(Section::Data("rdata")),
+            _ => None,
+        };
+
+        if let Some(new_section) = switch_to.filter(|_| !in_procedure) {
+            flush(&section, &mut pending, &mut chunks);
+            section = new_section;
+            pending.push(asm);
+            continue;
+        }
+
+        match asm {
+            Asm::ient { .. } if !in_procedure => {
+                in_procedure = true;
+                section = Section::Text;
+                pending.push(asm);
+            }
+            Asm::iend { symno } if in_procedure => {
+                in_procedure = false;
+                pending.push(asm);
+                chunks.push(Chunk {
+                    name: symbol_name(symno, st),
+                    records: std::mem::take(&mut pending),
+                });
+            }
+            _ => pending.push(asm),
+        }
+    }
+    flush(&section, &mut pending, &mut chunks);
+
+    chunks
+}
+
+// Writes each procedure and data run of a binasm file to its own .s file in `out_dir`. Chunks whose
+// file names would clash get a numeric suffix. Returns the number of files written.
+pub fn split_binasm(bytes: &[u8], st: Option<&SymbolTable>, out_dir: &Path) -> io::Result<usize> {
+    fs::create_dir_all(out_dir)?;
+    let chunks = split_records(read_records(bytes), st);
+    let mut used: HashSet<String> = HashSet::new();
+
+    for chunk in &chunks {
+        let mut text = String::new();
+        for asm in &chunk.records {
+            let line = asm.to_string();
+            let line = match st {
+                Some(st) => replace_symbol_names(&line, |n| st.dense_name(n as u32)),
+                None => line,
+            };
+            text.push_str(&line);
+            text.push('\n');
+        }
+        let stem = file_stem(&chunk.name);
+        let mut file_name = format!("{stem}.s");
+        let mut n = 1;
+        while !used.insert(file_name.clone()) {
+            file_name = format!("{stem}_{n}.s");
+            n += 1;
+        }
+        fs::write(out_dir.join(file_name), text)?;
+    }
+
+    Ok(chunks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::*;
+    use super::*;
+
+    #[test]
+    fn splits_procedures_and_data() {
+        let file = program(Endian::Big)
+            .data()
+            .word(1, 0)
+            .word(2, 0)
+            .sdata()
+            .word(0, 5);
+        let chunks = split_records(file.records(), None);
+        let names: Vec<_> = chunks.iter().map(|chunk| chunk.name.as_str()).collect();
+        assert_eq!(names, ["SYM_1", "SYM_2", "data_0", "sdata_0"]);
+        // The .text and .globl before main stay with it
+        assert!(matches!(
+            chunks[0].records[..2],
+            [Asm::itext, Asm::iglobal { symno: 1 }]
+        ));
+    }
+
+    #[test]
+    fn keeps_file_names_in_the_directory() {
+        assert_eq!(file_stem("main"), "main");
+        assert_eq!(file_stem("../x"), "_._x");
+        assert_eq!(file_stem(".L1"), "_L1");
+        assert_eq!(file_stem(""), "_");
+    }
+}
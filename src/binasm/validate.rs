@@ -0,0 +1,52 @@
+// Checks that every record decodes and that each instruction's operands were decoded with a format
+// its opcode can take. A failure points at a binasm file written by something other than the
+// compiler, or at a record layout this decoder does not understand.
+
+use super::opinfo::op_info;
+use super::*;
+
+// Prints a warning for each record that cannot be decoded, and for each instruction whose operand
+// format is illegal for its opcode or whose opcode is a slot with no known instruction. Returns the
+// number of warnings.
+pub fn validate_binasm(bytes: &[u8]) -> usize {
+    let (records, errors) = decode_records(bytes);
+    let mut warnings: Vec<(usize, String)> = errors
+        .into_iter()
+        .map(|(off, err)| (off, err.to_string()))
+        .collect();
+
+    for record in records {
+        let Asm::iocode { op, args } = &record.asm else {
+            continue;
+        };
+        let info = op_info(*op);
+        if info.unknown {
+            warnings.push((
+                record.offset,
+                format!("opcode slot {} has no known instruction", *op as usize),
+            ));
+            continue;
+        }
+        let form = args.format();
+        // Opcodes whose operands are not modelled take any format
+        if info.formats.is_empty() || info.formats.contains(&form) {
+            continue;
+        }
+
+        let expected: Vec<String> = info.formats.iter().map(|f| format!("{f:?}")).collect();
+        warnings.push((
+            record.offset,
+            format!(
+                "{} has {form:?} operands, expected {}",
+                op.to_string().trim_end(),
+                expected.join(" or ")
+            ),
+        ));
+    }
+
+    warnings.sort_by_key(|&(off, _)| off);
+    for (off, warning) in &warnings {
+        println!("0x{off:06X}: {warning}");
+    }
+    warnings.len()
+}
@@ -1,5 +1,9 @@
 // Using anything from this file is optional
-#![allow(dead_code)]
+#![allow(
+    dead_code,
+    clippy::manual_range_contains,
+    clippy::manual_is_multiple_of
+)]
 
 fn make_printable(c: u8) -> char {
     if 0x20 <= c && c <= 0x7E {
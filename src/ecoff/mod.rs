@@ -0,0 +1,413 @@
+// Reader for MIPS ECOFF object files, as produced by older IDO and the Ultrix and RISC/os
+// toolchains
+#![allow(dead_code)]
+
+use std::fmt;
+
+const FILHDR_LENGTH: usize = 0x14;
+const AOUTHDR_LENGTH: usize = 0x38;
+const SCNHDR_LENGTH: usize = 0x28;
+const RELOC_LENGTH: usize = 0x8;
+
+// File header magic numbers, as read in the file's own byte order
+pub const MIPSEBMAGIC: u16 = 0x0160;
+pub const MIPSELMAGIC: u16 = 0x0162;
+pub const MIPSEBMAGIC_2: u16 = 0x0163;
+pub const MIPSELMAGIC_2: u16 = 0x0166;
+pub const MIPSEBMAGIC_3: u16 = 0x0140;
+pub const MIPSELMAGIC_3: u16 = 0x0142;
+
+const BIG_ENDIAN_MAGICS: [u16; 3] = [MIPSEBMAGIC, MIPSEBMAGIC_2, MIPSEBMAGIC_3];
+const LITTLE_ENDIAN_MAGICS: [u16; 3] = [MIPSELMAGIC, MIPSELMAGIC_2, MIPSELMAGIC_3];
+
+// Relocation types
+pub const R_ABS: u32 = 0;
+pub const R_REFHALF: u32 = 1;
+pub const R_REFWORD: u32 = 2;
+pub const R_JMPADDR: u32 = 3;
+pub const R_REFHI: u32 = 4;
+pub const R_REFLO: u32 = 5;
+pub const R_GPREL: u32 = 6;
+pub const R_LITERAL: u32 = 7;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn u16(&self, off: usize) -> u16 {
+        let b = self.bytes[off..off + 2].try_into().unwrap();
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+    fn u32(&self, off: usize) -> u32 {
+        let b = self.bytes[off..off + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileHeader {
+    pub f_magic: u16,
+    pub f_nscns: u16,
+    pub f_timdat: u32,
+    pub f_symptr: u32,
+    pub f_nsyms: u32,
+    pub f_opthdr: u16,
+    pub f_flags: u16,
+}
+
+// a.out optional header
+#[derive(Debug)]
+pub struct AoutHeader {
+    pub magic: u16,
+    pub vstamp: u16,
+    pub tsize: u32,
+    pub dsize: u32,
+    pub bsize: u32,
+    pub entry: u32,
+    pub text_start: u32,
+    pub data_start: u32,
+    pub bss_start: u32,
+    pub gprmask: u32,
+    pub cprmask: [u32; 4],
+    pub gp_value: u32,
+}
+
+#[derive(Debug)]
+pub struct SectionHeader {
+    pub s_name: String,
+    pub s_paddr: u32,
+    pub s_vaddr: u32,
+    pub s_size: u32,
+    pub s_scnptr: u32,
+    pub s_relptr: u32,
+    pub s_lnnoptr: u32,
+    pub s_nreloc: u16,
+    pub s_nlnno: u16,
+    pub s_flags: u32,
+}
+
+#[derive(Debug)]
+pub struct Reloc {
+    pub r_vaddr: u32,
+    pub r_symndx: u32,
+    pub r_type: u32,
+    pub r_extern: bool,
+}
+
+pub struct Ecoff<'a> {
+    bytes: &'a [u8],
+    pub big_endian: bool,
+    pub header: FileHeader,
+    pub aout: Option<AoutHeader>,
+    pub sections: Vec<SectionHeader>,
+}
+
+fn file_magic(bytes: &[u8]) -> Option<bool> {
+    if bytes.len() < FILHDR_LENGTH {
+        return None;
+    }
+    if BIG_ENDIAN_MAGICS.contains(&u16::from_be_bytes([bytes[0], bytes[1]])) {
+        Some(true)
+    } else if LITTLE_ENDIAN_MAGICS.contains(&u16::from_le_bytes([bytes[0], bytes[1]])) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+pub fn is_ecoff(bytes: &[u8]) -> bool {
+    file_magic(bytes).is_some()
+}
+
+impl<'a> Ecoff<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Option<Ecoff<'a>> {
+        let big_endian = file_magic(bytes)?;
+        let r = Reader { bytes, big_endian };
+
+        let header = FileHeader {
+            f_magic: r.u16(0x0),
+            f_nscns: r.u16(0x2),
+            f_timdat: r.u32(0x4),
+            f_symptr: r.u32(0x8),
+            f_nsyms: r.u32(0xC),
+            f_opthdr: r.u16(0x10),
+            f_flags: r.u16(0x12),
+        };
+
+        let scnhdr_start = FILHDR_LENGTH + header.f_opthdr as usize;
+        if bytes.len() < scnhdr_start + header.f_nscns as usize * SCNHDR_LENGTH {
+            return None;
+        }
+
+        let aout = (header.f_opthdr as usize >= AOUTHDR_LENGTH).then(|| {
+            let off = FILHDR_LENGTH;
+            AoutHeader {
+                magic: r.u16(off),
+                vstamp: r.u16(off + 0x2),
+                tsize: r.u32(off + 0x4),
+                dsize: r.u32(off + 0x8),
+                bsize: r.u32(off + 0xC),
+                entry: r.u32(off + 0x10),
+                text_start: r.u32(off + 0x14),
+                data_start: r.u32(off + 0x18),
+                bss_start: r.u32(off + 0x1C),
+                gprmask: r.u32(off + 0x20),
+                cprmask: [
+                    r.u32(off + 0x24),
+                    r.u32(off + 0x28),
+                    r.u32(off + 0x2C),
+                    r.u32(off + 0x30),
+                ],
+                gp_value: r.u32(off + 0x34),
+            }
+        });
+
+        let sections = (0..header.f_nscns as usize)
+            .map(|i| {
+                let off = scnhdr_start + i * SCNHDR_LENGTH;
+                let name = &bytes[off..off + 8];
+                let end = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+                SectionHeader {
+                    s_name: String::from_utf8_lossy(&name[..end]).into_owned(),
+                    s_paddr: r.u32(off + 0x8),
+                    s_vaddr: r.u32(off + 0xC),
+                    s_size: r.u32(off + 0x10),
+                    s_scnptr: r.u32(off + 0x14),
+                    s_relptr: r.u32(off + 0x18),
+                    s_lnnoptr: r.u32(off + 0x1C),
+                    s_nreloc: r.u16(off + 0x20),
+                    s_nlnno: r.u16(off + 0x22),
+                    s_flags: r.u32(off + 0x24),
+                }
+            })
+            .collect();
+
+        Some(Ecoff {
+            bytes,
+            big_endian,
+            header,
+            aout,
+            sections,
+        })
+    }
+
+    pub fn section(&self, name: &str) -> Option<&SectionHeader> {
+        self.sections.iter().find(|s| s.s_name == name)
+    }
+
+    // Contents of a section, empty for sections that take no space in the file. None if the
+    // section extends past the end of the file.
+    pub fn section_data(&self, section: &SectionHeader) -> Option<&'a [u8]> {
+        if section.s_scnptr == 0 {
+            return Some(&[]);
+        }
+        let start = section.s_scnptr as usize;
+        self.bytes.get(start..start + section.s_size as usize)
+    }
+
+    // Relocations of a section, none if they extend past the end of the file
+    pub fn relocations(&self, section: &SectionHeader) -> Vec<Reloc> {
+        let start = section.s_relptr as usize;
+        let Some(bytes) = self
+            .bytes
+            .get(start..start + section.s_nreloc as usize * RELOC_LENGTH)
+        else {
+            return Vec::new();
+        };
+        let r = Reader {
+            bytes,
+            big_endian: self.big_endian,
+        };
+        (0..section.s_nreloc as usize)
+            .map(|i| {
+                let off = i * RELOC_LENGTH;
+                let bits = r.u32(off + 4);
+                // The bitfields are allocated from the opposite end in little-endian files
+                let (r_symndx, r_type, r_extern) = if self.big_endian {
+                    (bits >> 8, (bits >> 1) & 0xF, bits & 1 != 0)
+                } else {
+                    (bits & 0xFFFFFF, (bits >> 27) & 0xF, bits >> 31 != 0)
+                };
+                Reloc {
+                    r_vaddr: r.u32(off),
+                    r_symndx,
+                    r_type,
+                    r_extern,
+                }
+            })
+            .collect()
+    }
+
+    // The symbolic header and everything after it, with the offset of that data in the file (the
+    // header's own offsets are relative to the start of the file)
+    pub fn symbolic_header(&self) -> Option<(&'a [u8], usize)> {
+        let symptr = self.header.f_symptr as usize;
+        if symptr == 0 || symptr >= self.bytes.len() {
+            return None;
+        }
+        Some((&self.bytes[symptr..], symptr))
+    }
+}
+
+impl fmt::Display for Reloc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r_type = match self.r_type {
+            R_ABS => "R_ABS",
+            R_REFHALF => "R_REFHALF",
+            R_REFWORD => "R_REFWORD",
+            R_JMPADDR => "R_JMPADDR",
+            R_REFHI => "R_REFHI",
+            R_REFLO => "R_REFLO",
+            R_GPREL => "R_GPREL",
+            R_LITERAL => "R_LITERAL",
+            _ => "R_?",
+        };
+        write!(f, "0x{:08X} {:10}", self.r_vaddr, r_type)?;
+        if self.r_extern {
+            write!(f, "ext {}", self.r_symndx)
+        } else {
+            write!(f, "sect {}", self.r_symndx)
+        }
+    }
+}
+
+pub fn print_ecoff(ecoff: &Ecoff) {
+    let h = &ecoff.header;
+    println!(
+        "magic 0x{:04X} ({}-endian), {} sections, symptr 0x{:X}, flags 0x{:04X}",
+        h.f_magic,
+        if ecoff.big_endian { "big" } else { "little" },
+        h.f_nscns,
+        h.f_symptr,
+        h.f_flags
+    );
+    if let Some(a) = &ecoff.aout {
+        println!(
+            "aout magic 0x{:04X} vstamp 0x{:04X} text 0x{:08X}+0x{:X} data 0x{:08X}+0x{:X} bss 0x{:08X}+0x{:X} entry 0x{:08X} gp 0x{:08X}",
+            a.magic, a.vstamp, a.text_start, a.tsize, a.data_start, a.dsize, a.bss_start, a.bsize, a.entry, a.gp_value
+        );
+    }
+    for section in &ecoff.sections {
+        println!(
+            "{:8} vaddr 0x{:08X} size 0x{:X} offset 0x{:X} relocs {} flags 0x{:08X}",
+            section.s_name,
+            section.s_vaddr,
+            section.s_size,
+            section.s_scnptr,
+            section.s_nreloc,
+            section.s_flags
+        );
+        for reloc in ecoff.relocations(section) {
+            println!("\t{reloc}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::st::{fixture::symbol_table, SymbolTable};
+
+    // An object with a .text section calling external symbol 1, followed by its symbol table
+    fn object(big_endian: bool) -> Vec<u8> {
+        let u16 = |v: u16| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let u32 = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let text: Vec<u8> = [0x27BDFFE8, 0x0C000000, 0x00000000, 0x03E00008]
+            .into_iter()
+            .flat_map(u32)
+            .collect();
+        let text_ptr = FILHDR_LENGTH + SCNHDR_LENGTH;
+        let reloc_ptr = text_ptr + text.len();
+        let symptr = reloc_ptr + RELOC_LENGTH;
+
+        let mut file = Vec::new();
+        let magic = if big_endian { MIPSEBMAGIC } else { MIPSELMAGIC };
+        file.extend(u16(magic));
+        file.extend(u16(1));
+        file.extend(u32(0));
+        file.extend(u32(symptr as u32));
+        file.extend(u32(0x60));
+        file.extend(u16(0));
+        file.extend(u16(0));
+
+        file.extend(b".text\0\0\0");
+        for value in [
+            0,
+            0,
+            text.len() as u32,
+            text_ptr as u32,
+            reloc_ptr as u32,
+            0,
+        ] {
+            file.extend(u32(value));
+        }
+        file.extend(u16(1));
+        file.extend(u16(0));
+        file.extend(u32(0x20));
+
+        file.extend(&text);
+        // R_JMPADDR against external symbol 1 at 4
+        let bits = if big_endian {
+            (1 << 8) | (R_JMPADDR << 1) | 1
+        } else {
+            1 | (R_JMPADDR << 27) | (1 << 31)
+        };
+        file.extend(u32(4));
+        file.extend(u32(bits));
+
+        file.extend(symbol_table(big_endian, symptr));
+        file
+    }
+
+    #[test]
+    fn reads_both_byte_orders() {
+        for big_endian in [true, false] {
+            let bytes = object(big_endian);
+            let ecoff = Ecoff::parse(&bytes).unwrap();
+            assert_eq!(ecoff.big_endian, big_endian);
+
+            let text = ecoff.section(".text").unwrap();
+            assert_eq!(ecoff.section_data(text).unwrap().len(), 0x10);
+            let relocs = ecoff.relocations(text);
+            assert_eq!(relocs.len(), 1);
+            assert_eq!(relocs[0].to_string(), "0x00000004 R_JMPADDR ext 1");
+
+            let st = SymbolTable::from_file(&bytes).unwrap();
+            assert_eq!(st.dense_name(3), Some("puts"));
+        }
+    }
+
+    #[test]
+    fn rejects_data_past_the_end() {
+        let bytes = object(true);
+        assert!(Ecoff::parse(&bytes[..FILHDR_LENGTH + 4]).is_none());
+
+        // Relocations past the end of the file
+        let bytes = &bytes[..FILHDR_LENGTH + SCNHDR_LENGTH + 0x14];
+        let ecoff = Ecoff::parse(bytes).unwrap();
+        assert!(ecoff.relocations(&ecoff.sections[0]).is_empty());
+        assert!(SymbolTable::from_file(bytes).is_none());
+    }
+}
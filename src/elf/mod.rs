@@ -0,0 +1,429 @@
+// Minimal reader for the 32-bit big-endian ELF files IRIX IDO produces
+#![allow(dead_code)]
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+const EHDR_LENGTH: usize = 0x34;
+const SHDR_LENGTH: usize = 0x28;
+const SYM_LENGTH: usize = 0x10;
+const REL_LENGTH: usize = 0x8;
+
+pub const EM_MIPS: u16 = 8;
+
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOBITS: u32 = 8;
+pub const SHT_REL: u32 = 9;
+pub const SHT_MIPS_DEBUG: u32 = 0x70000005;
+
+pub const STT_NOTYPE: u8 = 0;
+pub const STT_OBJECT: u8 = 1;
+pub const STT_FUNC: u8 = 2;
+pub const STT_SECTION: u8 = 3;
+
+// Relocation types
+pub const R_MIPS_NONE: u32 = 0;
+pub const R_MIPS_16: u32 = 1;
+pub const R_MIPS_32: u32 = 2;
+pub const R_MIPS_REL32: u32 = 3;
+pub const R_MIPS_26: u32 = 4;
+pub const R_MIPS_HI16: u32 = 5;
+pub const R_MIPS_LO16: u32 = 6;
+pub const R_MIPS_GPREL16: u32 = 7;
+pub const R_MIPS_LITERAL: u32 = 8;
+pub const R_MIPS_GOT16: u32 = 9;
+pub const R_MIPS_PC16: u32 = 10;
+pub const R_MIPS_CALL16: u32 = 11;
+pub const R_MIPS_GPREL32: u32 = 12;
+
+fn read_u16(bytes: &[u8], off: usize) -> u16 {
+    u16::from_be_bytes(bytes[off..off + 2].try_into().unwrap())
+}
+fn read_u32(bytes: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes(bytes[off..off + 4].try_into().unwrap())
+}
+
+#[derive(Debug)]
+pub struct FileHeader {
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub e_version: u32,
+    pub e_entry: u32,
+    pub e_phoff: u32,
+    pub e_shoff: u32,
+    pub e_flags: u32,
+    pub e_ehsize: u16,
+    pub e_phentsize: u16,
+    pub e_phnum: u16,
+    pub e_shentsize: u16,
+    pub e_shnum: u16,
+    pub e_shstrndx: u16,
+}
+
+#[derive(Debug)]
+pub struct SectionHeader {
+    pub name: String,
+    pub sh_name: u32,
+    pub sh_type: u32,
+    pub sh_flags: u32,
+    pub sh_addr: u32,
+    pub sh_offset: u32,
+    pub sh_size: u32,
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_addralign: u32,
+    pub sh_entsize: u32,
+}
+
+impl SectionHeader {
+    fn read(bytes: &[u8]) -> SectionHeader {
+        let w = |i: usize| read_u32(bytes, 4 * i);
+        SectionHeader {
+            name: String::new(),
+            sh_name: w(0),
+            sh_type: w(1),
+            sh_flags: w(2),
+            sh_addr: w(3),
+            sh_offset: w(4),
+            sh_size: w(5),
+            sh_link: w(6),
+            sh_info: w(7),
+            sh_addralign: w(8),
+            sh_entsize: w(9),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub st_value: u32,
+    pub st_size: u32,
+    pub st_info: u8,
+    pub st_other: u8,
+    pub st_shndx: u16,
+}
+
+impl Symbol {
+    pub fn st_type(&self) -> u8 {
+        self.st_info & 0xF
+    }
+}
+
+#[derive(Debug)]
+pub struct Rel {
+    pub r_offset: u32,
+    pub r_sym: u32,
+    pub r_type: u32,
+}
+
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    pub header: FileHeader,
+    pub sections: Vec<SectionHeader>,
+}
+
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.len() >= EHDR_LENGTH && bytes[0..4] == ELF_MAGIC
+}
+
+impl<'a> Elf<'a> {
+    // Returns None for anything other than a 32-bit big-endian ELF file
+    pub fn parse(bytes: &'a [u8]) -> Option<Elf<'a>> {
+        if !is_elf(bytes) || bytes[4] != ELFCLASS32 || bytes[5] != ELFDATA2MSB {
+            return None;
+        }
+        let header = FileHeader {
+            e_type: read_u16(bytes, 0x10),
+            e_machine: read_u16(bytes, 0x12),
+            e_version: read_u32(bytes, 0x14),
+            e_entry: read_u32(bytes, 0x18),
+            e_phoff: read_u32(bytes, 0x1C),
+            e_shoff: read_u32(bytes, 0x20),
+            e_flags: read_u32(bytes, 0x24),
+            e_ehsize: read_u16(bytes, 0x28),
+            e_phentsize: read_u16(bytes, 0x2A),
+            e_phnum: read_u16(bytes, 0x2C),
+            e_shentsize: read_u16(bytes, 0x2E),
+            e_shnum: read_u16(bytes, 0x30),
+            e_shstrndx: read_u16(bytes, 0x32),
+        };
+
+        let shoff = header.e_shoff as usize;
+        let mut sections: Vec<SectionHeader> = bytes
+            .get(shoff..shoff + header.e_shnum as usize * SHDR_LENGTH)?
+            .chunks_exact(SHDR_LENGTH)
+            .map(SectionHeader::read)
+            .collect();
+
+        if let Some(shstr) = sections.get(header.e_shstrndx as usize) {
+            let start = shstr.sh_offset as usize;
+            let strings = bytes.get(start..start + shstr.sh_size as usize)?;
+            for section in sections.iter_mut() {
+                section.name = read_string(strings, section.sh_name as usize);
+            }
+        }
+
+        Some(Elf {
+            bytes,
+            header,
+            sections,
+        })
+    }
+
+    pub fn section(&self, name: &str) -> Option<&SectionHeader> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    // Contents of a section, empty for .bss-like sections that take no space in the file. None if
+    // the section extends past the end of the file.
+    pub fn section_data(&self, section: &SectionHeader) -> Option<&'a [u8]> {
+        if section.sh_type == SHT_NOBITS {
+            return Some(&[]);
+        }
+        let start = section.sh_offset as usize;
+        self.bytes.get(start..start + section.sh_size as usize)
+    }
+
+    // Entries of the static symbol table, starting with the null symbol so that relocations can
+    // index it directly
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let Some(symtab) = self.sections.iter().find(|s| s.sh_type == SHT_SYMTAB) else {
+            return Vec::new();
+        };
+        let strings = self
+            .sections
+            .get(symtab.sh_link as usize)
+            .and_then(|s| self.section_data(s))
+            .unwrap_or_default();
+        self.section_data(symtab)
+            .unwrap_or_default()
+            .chunks_exact(SYM_LENGTH)
+            .map(|b| Symbol {
+                name: read_string(strings, read_u32(b, 0) as usize),
+                st_value: read_u32(b, 4),
+                st_size: read_u32(b, 8),
+                st_info: b[12],
+                st_other: b[13],
+                st_shndx: read_u16(b, 14),
+            })
+            .collect()
+    }
+
+    // Relocations that apply to a section, from the SHT_REL sections pointing at it
+    pub fn relocations(&self, section: &SectionHeader) -> Vec<Rel> {
+        let Some(index) = self.sections.iter().position(|s| std::ptr::eq(s, section)) else {
+            return Vec::new();
+        };
+        self.sections
+            .iter()
+            .filter(|s| s.sh_type == SHT_REL && s.sh_info as usize == index)
+            .flat_map(|s| {
+                self.section_data(s)
+                    .unwrap_or_default()
+                    .chunks_exact(REL_LENGTH)
+            })
+            .map(|b| {
+                let info = read_u32(b, 4);
+                Rel {
+                    r_offset: read_u32(b, 0),
+                    r_sym: info >> 8,
+                    r_type: info & 0xFF,
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn reloc_type_name(r_type: u32) -> &'static str {
+    match r_type {
+        R_MIPS_NONE => "R_MIPS_NONE",
+        R_MIPS_16 => "R_MIPS_16",
+        R_MIPS_32 => "R_MIPS_32",
+        R_MIPS_REL32 => "R_MIPS_REL32",
+        R_MIPS_26 => "R_MIPS_26",
+        R_MIPS_HI16 => "R_MIPS_HI16",
+        R_MIPS_LO16 => "R_MIPS_LO16",
+        R_MIPS_GPREL16 => "R_MIPS_GPREL16",
+        R_MIPS_LITERAL => "R_MIPS_LITERAL",
+        R_MIPS_GOT16 => "R_MIPS_GOT16",
+        R_MIPS_PC16 => "R_MIPS_PC16",
+        R_MIPS_CALL16 => "R_MIPS_CALL16",
+        R_MIPS_GPREL32 => "R_MIPS_GPREL32",
+        _ => "R_MIPS_?",
+    }
+}
+
+fn read_string(strings: &[u8], off: usize) -> String {
+    let s = &strings[off.min(strings.len())..];
+    let end = s.iter().position(|c| *c == 0).unwrap_or(s.len());
+    String::from_utf8_lossy(&s[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::st::{fixture::symbol_table, SymbolTable};
+
+    // Section name, type, link, info, entry size and contents
+    type Section = (&'static str, u32, u32, u32, u32, Vec<u8>);
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    // A relocatable object with the sections given, after the null section and before .shstrtab.
+    // `mdebug` makes the last section a symbol table with offsets counted from the file's start.
+    fn object(sections: &[Section], mdebug: bool) -> Vec<u8> {
+        let mut shstrtab = b"\0.shstrtab\0".to_vec();
+        let mut body = Vec::new();
+        let mut headers = vec![0; SHDR_LENGTH];
+        let mut section_header = |name: u32, sh_type, offset, size, link, info, entsize| {
+            headers.extend(words(&[
+                name, sh_type, 0, 0, offset, size, link, info, 4, entsize,
+            ]));
+        };
+        for (k, (name, sh_type, link, info, entsize, data)) in sections.iter().enumerate() {
+            let offset = EHDR_LENGTH + body.len();
+            let data = if mdebug && k == sections.len() - 1 {
+                symbol_table(true, offset)
+            } else {
+                data.clone()
+            };
+            section_header(
+                shstrtab.len() as u32,
+                *sh_type,
+                offset as u32,
+                data.len() as u32,
+                *link,
+                *info,
+                *entsize,
+            );
+            shstrtab.extend(name.bytes());
+            shstrtab.push(0);
+            body.extend(data);
+        }
+        section_header(
+            1,
+            SHT_STRTAB,
+            (EHDR_LENGTH + body.len()) as u32,
+            shstrtab.len() as u32,
+            0,
+            0,
+            0,
+        );
+        body.extend(&shstrtab);
+        while body.len() % 4 != 0 {
+            body.push(0);
+        }
+
+        let shnum = sections.len() as u16 + 2;
+        let mut file = ELF_MAGIC.to_vec();
+        file.extend([ELFCLASS32, ELFDATA2MSB, 1]);
+        file.resize(0x10, 0);
+        file.extend(1u16.to_be_bytes());
+        file.extend(EM_MIPS.to_be_bytes());
+        file.extend(words(&[1, 0, 0, (EHDR_LENGTH + body.len()) as u32, 0]));
+        for half in [
+            EHDR_LENGTH as u16,
+            0,
+            0,
+            SHDR_LENGTH as u16,
+            shnum,
+            shnum - 1,
+        ] {
+            file.extend(half.to_be_bytes());
+        }
+        file.extend(body);
+        file.extend(headers);
+        file
+    }
+
+    // .text calling puts, with its symbol table and relocations, and the .mdebug symbol table
+    fn sample() -> Vec<u8> {
+        let symbol = |name: u32, value: u32, size: u32, info: u8, shndx: u16| {
+            let mut b = words(&[name, value, size]);
+            b.extend([info, 0]);
+            b.extend(shndx.to_be_bytes());
+            b
+        };
+        let symtab = [
+            symbol(0, 0, 0, 0, 0),
+            symbol(1, 0, 0x10, 0x12, 1),
+            symbol(6, 0, 0, 0x10, 0),
+        ]
+        .concat();
+        object(
+            &[
+                (
+                    ".text",
+                    SHT_PROGBITS,
+                    0,
+                    0,
+                    0,
+                    words(&[0x27BDFFE8, 0x0C000000, 0x00000000, 0x03E00008]),
+                ),
+                (".symtab", SHT_SYMTAB, 3, 1, SYM_LENGTH as u32, symtab),
+                (".strtab", SHT_STRTAB, 0, 0, 0, b"\0main\0puts\0".to_vec()),
+                (
+                    ".rel.text",
+                    SHT_REL,
+                    2,
+                    1,
+                    REL_LENGTH as u32,
+                    words(&[4, (2 << 8) | R_MIPS_26]),
+                ),
+                (".mdebug", SHT_MIPS_DEBUG, 0, 0, 0, Vec::new()),
+            ],
+            true,
+        )
+    }
+
+    #[test]
+    fn reads_sections_symbols_and_relocations() {
+        let bytes = sample();
+        let elf = Elf::parse(&bytes).unwrap();
+        assert_eq!(elf.header.e_machine, EM_MIPS);
+
+        let text = elf.section(".text").unwrap();
+        assert_eq!(
+            &elf.section_data(text).unwrap()[..4],
+            &[0x27, 0xBD, 0xFF, 0xE8]
+        );
+
+        let symbols = elf.symbols();
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["", "main", "puts"]);
+        assert_eq!(symbols[1].st_type(), STT_FUNC);
+
+        let relocations = elf.relocations(text);
+        assert_eq!(relocations.len(), 1);
+        let rel = &relocations[0];
+        assert_eq!((rel.r_offset, rel.r_sym, rel.r_type), (4, 2, R_MIPS_26));
+    }
+
+    #[test]
+    fn reads_the_mdebug_symbol_table() {
+        let st = SymbolTable::from_file(&sample()).unwrap();
+        assert_eq!(st.dense_name(1), Some("main"));
+        assert_eq!(st.procedure(1).unwrap().frameoffset, 24);
+    }
+
+    #[test]
+    fn rejects_data_past_the_end() {
+        let mut bytes = sample();
+        // Section headers past the end of the file
+        bytes.truncate(bytes.len() - 1);
+        assert!(Elf::parse(&bytes).is_none());
+
+        // .text extending past the end of the file
+        let mut bytes = sample();
+        let text_header = bytes.len() - 6 * SHDR_LENGTH;
+        bytes[text_header + 0x14..text_header + 0x18].copy_from_slice(&0x10000u32.to_be_bytes());
+        let elf = Elf::parse(&bytes).unwrap();
+        assert!(elf.section_data(elf.section(".text").unwrap()).is_none());
+    }
+}
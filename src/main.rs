@@ -2,18 +2,168 @@ use std::fs::read;
 
 mod binasm;
 mod debugging;
+mod ecoff;
+mod elf;
+mod st;
 
-fn main() -> () {
+fn usage(prog: &str) {
+    eprintln!("USAGE: {prog} FILE");
+    eprintln!("       {prog} binasm dump FILE.G");
+    eprintln!("       {prog} binasm check-pdr FILE.G FILE.T|FILE.o");
+    eprintln!("       {prog} binasm validate FILE.G");
+    eprintln!("       {prog} binasm check-prologue FILE.G [FILE.T]");
+    eprintln!("       {prog} binasm diff A.G B.G [A.T B.T]");
+    eprintln!("       {prog} binasm split FILE.G OUTDIR [FILE.T]");
+    eprintln!("       {prog} binasm cfg FILE.G [FILE.T]");
+    eprintln!("       {prog} binasm livereg FILE.G [FILE.T]");
+    eprintln!("       {prog} binasm expand FILE.G [-mips1|-mips2|-mips3|-mips4]");
+    eprintln!("       {prog} binasm encode FILE.G [-mips1|-mips2|-mips3|-mips4]");
+    eprintln!("       {prog} binasm disasm FILE.o");
+    eprintln!("       {prog} st dump FILE.T|FILE.o");
+    eprintln!("       {prog} ecoff dump FILE.o");
+}
+
+fn main() {
     let argv: Vec<String> = std::env::args().collect();
-    if argv.len() < 2 {
-        eprintln!("USAGE: {} FILE", argv[0]);
-        return;
-    }
-    let in_filepath = &argv[1];
-    let bytes = read(in_filepath).unwrap();
+    let args: Vec<&str> = argv.iter().map(|s| s.as_str()).collect();
+
+    match args[1..] {
+        ["binasm", "dump", in_filepath] | [in_filepath] => {
+            let bytes = read(in_filepath).unwrap();
+
+            // println!("Dump of contents of {}", in_filepath);
+            // debugging::print_bytes(&bytes);
+
+            binasm::process_records(&bytes);
+        }
+        ["binasm", "check-pdr", binasm_filepath, st_filepath] => {
+            let bytes = read(binasm_filepath).unwrap();
+            let st_bytes = read(st_filepath).unwrap();
+            let st = st::SymbolTable::from_file(&st_bytes).expect("not a symbol table");
+
+            let mismatches = binasm::check_pdrs(&bytes, &st);
+            println!("{mismatches} mismatches");
+            if mismatches != 0 {
+                std::process::exit(1);
+            }
+        }
+        ["binasm", "check-prologue", binasm_filepath, ref st_filepath @ ..]
+            if st_filepath.len() <= 1 =>
+        {
+            let bytes = read(binasm_filepath).unwrap();
+            let st = st_filepath.first().map(|path| {
+                let bytes = read(path).unwrap();
+                st::SymbolTable::from_file(&bytes).expect("not a symbol table")
+            });
+
+            let mismatches = binasm::check_prologues(&bytes, st.as_ref());
+            println!("{mismatches} mismatches");
+            if mismatches != 0 {
+                std::process::exit(1);
+            }
+        }
+        ["binasm", "validate", binasm_filepath] => {
+            let bytes = read(binasm_filepath).unwrap();
 
-    // println!("Dump of contents of {}", in_filepath);
-    // debugging::print_bytes(&bytes);
+            let warnings = binasm::validate_binasm(&bytes);
+            println!("{warnings} warnings");
+            if warnings != 0 {
+                std::process::exit(1);
+            }
+        }
+        ["binasm", "diff", a_filepath, b_filepath, ref st_filepaths @ ..]
+            if st_filepaths.is_empty() || st_filepaths.len() == 2 =>
+        {
+            let a_bytes = read(a_filepath).unwrap();
+            let b_bytes = read(b_filepath).unwrap();
+            let sts: Vec<st::SymbolTable> = st_filepaths
+                .iter()
+                .map(|path| {
+                    let bytes = read(path).unwrap();
+                    st::SymbolTable::from_file(&bytes).expect("not a symbol table")
+                })
+                .collect();
 
-    binasm::process_records(&bytes);
+            let differences = binasm::diff_binasm(&a_bytes, &b_bytes, sts.first(), sts.get(1));
+            if differences != 0 {
+                std::process::exit(1);
+            }
+        }
+        ["binasm", "split", in_filepath, out_dir, ref st_filepath @ ..]
+            if st_filepath.len() <= 1 =>
+        {
+            let bytes = read(in_filepath).unwrap();
+            let st = st_filepath.first().map(|path| {
+                let bytes = read(path).unwrap();
+                st::SymbolTable::from_file(&bytes).expect("not a symbol table")
+            });
+
+            let count =
+                binasm::split_binasm(&bytes, st.as_ref(), std::path::Path::new(out_dir)).unwrap();
+            println!("Wrote {count} files to {out_dir}");
+        }
+        ["binasm", "cfg", in_filepath, ref st_filepath @ ..] if st_filepath.len() <= 1 => {
+            let bytes = read(in_filepath).unwrap();
+            let st = st_filepath.first().map(|path| {
+                let bytes = read(path).unwrap();
+                st::SymbolTable::from_file(&bytes).expect("not a symbol table")
+            });
+
+            binasm::print_cfgs(&bytes, st.as_ref());
+        }
+        ["binasm", "livereg", in_filepath, ref st_filepath @ ..] if st_filepath.len() <= 1 => {
+            let bytes = read(in_filepath).unwrap();
+            let st = st_filepath.first().map(|path| {
+                let bytes = read(path).unwrap();
+                st::SymbolTable::from_file(&bytes).expect("not a symbol table")
+            });
+
+            let mismatches = binasm::check_liveregs(&bytes, st.as_ref());
+            println!("{mismatches} mismatches");
+            if mismatches != 0 {
+                std::process::exit(1);
+            }
+        }
+        ["binasm", cmd @ ("expand" | "encode"), in_filepath, ref isa @ ..] if isa.len() <= 1 => {
+            let isa = match isa.first() {
+                None | Some(&"-mips1") => binasm::Isa::Mips1,
+                Some(&"-mips2") => binasm::Isa::Mips2,
+                Some(&"-mips3") => binasm::Isa::Mips3,
+                Some(&"-mips4") => binasm::Isa::Mips4,
+                Some(_) => {
+                    usage(&argv[0]);
+                    std::process::exit(1);
+                }
+            };
+            let bytes = read(in_filepath).unwrap();
+
+            if cmd == "expand" {
+                binasm::print_expanded(&bytes, isa);
+            } else if binasm::print_encoded(&bytes, isa) > 0 {
+                std::process::exit(1);
+            }
+        }
+        ["binasm", "disasm", in_filepath] => {
+            let bytes = read(in_filepath).unwrap();
+
+            let undecoded = binasm::disassemble_elf(&bytes).expect("not a big-endian ELF object");
+            if undecoded != 0 {
+                println!("{undecoded} words not decoded");
+                std::process::exit(1);
+            }
+        }
+        ["st", "dump", in_filepath] => {
+            let bytes = read(in_filepath).unwrap();
+            let st = st::SymbolTable::from_file(&bytes).expect("not a symbol table");
+
+            st::print_symbol_table(&st);
+        }
+        ["ecoff", "dump", in_filepath] => {
+            let bytes = read(in_filepath).unwrap();
+            let ecoff = ecoff::Ecoff::parse(&bytes).expect("not an ECOFF file");
+
+            ecoff::print_ecoff(&ecoff);
+        }
+        _ => usage(&argv[0]),
+    }
 }
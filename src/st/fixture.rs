@@ -0,0 +1,157 @@
+// Symbol tables for tests: one file, f.c, defining the external procedure main and the static
+// procedure helper, and referring to the undefined external puts. Dense numbers 1 to 3 are main,
+// helper and puts.
+
+use super::*;
+
+const ST_PROC: u32 = 6;
+const ST_FILE: u32 = 11;
+const ST_STATIC_PROC: u32 = 14;
+const SC_TEXT: u32 = 1;
+const SC_UNDEFINED: u32 = 6;
+
+struct Writer {
+    bytes: Vec<u8>,
+    big_endian: bool,
+}
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend(if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+
+    // Bitfields `(value, width)` in allocation order, packed into a `width`-bit word
+    fn bits(&self, width: u32, fields: &[(u32, u32)]) -> u32 {
+        let mut word = 0;
+        let mut offset = 0;
+        for (value, count) in fields {
+            let shift = if self.big_endian {
+                width - offset - count
+            } else {
+                offset
+            };
+            word |= value << shift;
+            offset += count;
+        }
+        word
+    }
+
+    fn symr(&mut self, iss: u32, value: u32, st: u32, sc: u32, index: u32) {
+        self.u32(iss);
+        self.u32(value);
+        let bits = self.bits(32, &[(st, 6), (sc, 5), (0, 1), (index, 20)]);
+        self.u32(bits);
+    }
+}
+
+// The table with its offsets counted from `base` bytes before it, as when it is embedded in an
+// object file
+pub fn symbol_table(big_endian: bool, base: usize) -> Vec<u8> {
+    let ss = b"\0f.c\0main\0helper\0";
+    let ssext = b"\0main\0puts\0";
+    let fd_offset = HDRR_LENGTH;
+    let pd_offset = fd_offset + FDR_LENGTH;
+    let sym_offset = pd_offset + 2 * PDR_LENGTH;
+    let ext_offset = sym_offset + 3 * SYMR_LENGTH;
+    let dn_offset = ext_offset + 2 * EXTR_LENGTH;
+    let ss_offset = dn_offset + 4 * DNR_LENGTH;
+    let ssext_offset = ss_offset + ss.len();
+
+    let mut w = Writer {
+        bytes: Vec::new(),
+        big_endian,
+    };
+    let at = |offset: usize| (base + offset) as u32;
+
+    // HDRR: the line number table, which is empty and the only one with a byte count, then the
+    // count and offset of the dense numbers, procedures, local symbols, optimization entries,
+    // auxiliary entries, strings, external strings, files, relative files and externals
+    w.u16(MAGIC_SYM);
+    w.u16(0x030B);
+    for value in [0, 0, 0] {
+        w.u32(value);
+    }
+    for (count, offset) in [
+        (4, at(dn_offset)),
+        (2, at(pd_offset)),
+        (3, at(sym_offset)),
+        (0, 0),
+        (0, 0),
+        (ss.len() as u32, at(ss_offset)),
+        (ssext.len() as u32, at(ssext_offset)),
+        (1, at(fd_offset)),
+        (0, 0),
+        (2, at(ext_offset)),
+    ] {
+        w.u32(count);
+        w.u32(offset);
+    }
+
+    // FDR of f.c: strings from 0, symbols from 0, procedures from 0
+    for value in [0, 1, 0, ss.len() as u32, 0, 3, 0, 0, 0, 0] {
+        w.u32(value);
+    }
+    w.u16(0);
+    w.u16(2);
+    for _ in 0..4 {
+        w.u32(0);
+    }
+    let bits = w.bits(
+        32,
+        &[(1, 5), (0, 1), (0, 1), (big_endian as u32, 1), (2, 2)],
+    );
+    w.u32(bits);
+    w.u32(0);
+    w.u32(0);
+
+    // PDRs of main, which saves $31 in a 24-byte frame, and helper, a leaf
+    for (isym, regmask, regoffset, frameoffset) in [(1, 0x8000_0000, -4, 24), (2, 0, 0, 0)] {
+        w.u32(0);
+        w.u32(isym);
+        w.u32(0);
+        w.u32(regmask);
+        w.u32(regoffset as u32);
+        w.u32(0);
+        w.u32(0);
+        w.u32(0);
+        w.u32(frameoffset);
+        w.u16(29);
+        w.u16(31);
+        w.u32(0);
+        w.u32(0);
+        w.u32(0);
+    }
+
+    w.symr(1, 0, ST_FILE, SC_TEXT, 3);
+    w.symr(5, 0, ST_PROC, SC_TEXT, 0);
+    w.symr(10, 0x40, ST_STATIC_PROC, SC_TEXT, 0);
+
+    // EXTRs of main, defined in file 0, and puts, defined nowhere
+    for (iss, ifd, sc) in [(1, 0, SC_TEXT), (6, -1i16, SC_UNDEFINED)] {
+        w.u16(0);
+        w.u16(ifd as u16);
+        w.symr(iss, 0, ST_PROC, sc, 0);
+    }
+
+    // Dense numbers: none at 0, then main, helper and puts
+    for (rfd, index) in [(0, 0), (ST_EXTIFD, 0), (0, 2), (ST_EXTIFD, 1)] {
+        w.u32(rfd);
+        w.u32(index);
+    }
+
+    w.bytes.extend(ss);
+    w.bytes.extend(ssext);
+    w.bytes
+}
@@ -0,0 +1,598 @@
+// Reader for the MIPS symbol table ("st"), as found in standalone .T files, in the .mdebug
+// section of ELF objects and after the sections of ECOFF objects. Field names follow IDO's sym.h.
+#![allow(non_snake_case)]
+#![allow(clippy::upper_case_acronyms)]
+#![allow(dead_code)]
+
+use std::fmt;
+
+use crate::ecoff::{self, Ecoff};
+use crate::elf::{self, Elf};
+
+#[cfg(test)]
+pub mod fixture;
+
+const MAGIC_SYM: u16 = 0x7009;
+const HDRR_LENGTH: usize = 0x60;
+const FDR_LENGTH: usize = 0x48;
+const PDR_LENGTH: usize = 0x34;
+const SYMR_LENGTH: usize = 0xC;
+const EXTR_LENGTH: usize = 0x10;
+const DNR_LENGTH: usize = 0x8;
+
+// rfd of a dense number that refers to the external symbol table
+pub const ST_EXTIFD: u32 = 0x7FFFFFFF;
+
+// One entry of a table, in the byte order of the symbol table. Big-endian compilers allocate
+// bitfields from the most significant bit and little-endian ones (DECstation Ultrix) from the
+// least.
+#[derive(Clone, Copy)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn u16(&self, off: usize) -> u16 {
+        let b = self.bytes[off..off + 2].try_into().unwrap();
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+    fn i16(&self, off: usize) -> i16 {
+        self.u16(off) as i16
+    }
+    fn u32(&self, off: usize) -> u32 {
+        let b = self.bytes[off..off + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+    fn i32(&self, off: usize) -> i32 {
+        self.u32(off) as i32
+    }
+
+    // Bitfield `count` bits wide, `offset` bits from the start of allocation in a `width`-bit word
+    fn get_bits(&self, word: u32, width: u32, offset: u32, count: u32) -> u32 {
+        let shift = if self.big_endian {
+            width - offset - count
+        } else {
+            offset
+        };
+        (word >> shift) & ((1 << count) - 1)
+    }
+}
+
+// Symbolic header
+#[derive(Debug)]
+pub struct HDRR {
+    pub magic: u16,
+    pub vstamp: u16,
+    pub ilineMax: i32,
+    pub cbLine: i32,
+    pub cbLineOffset: i32,
+    pub idnMax: i32,
+    pub cbDnOffset: i32,
+    pub ipdMax: i32,
+    pub cbPdOffset: i32,
+    pub isymMax: i32,
+    pub cbSymOffset: i32,
+    pub ioptMax: i32,
+    pub cbOptOffset: i32,
+    pub iauxMax: i32,
+    pub cbAuxOffset: i32,
+    pub issMax: i32,
+    pub cbSsOffset: i32,
+    pub issExtMax: i32,
+    pub cbSsExtOffset: i32,
+    pub ifdMax: i32,
+    pub cbFdOffset: i32,
+    pub crfd: i32,
+    pub cbRfdOffset: i32,
+    pub iextMax: i32,
+    pub cbExtOffset: i32,
+}
+
+impl HDRR {
+    fn read(r: Reader) -> HDRR {
+        let w = |i: usize| r.i32(4 + 4 * i);
+        HDRR {
+            magic: r.u16(0),
+            vstamp: r.u16(2),
+            ilineMax: w(0),
+            cbLine: w(1),
+            cbLineOffset: w(2),
+            idnMax: w(3),
+            cbDnOffset: w(4),
+            ipdMax: w(5),
+            cbPdOffset: w(6),
+            isymMax: w(7),
+            cbSymOffset: w(8),
+            ioptMax: w(9),
+            cbOptOffset: w(10),
+            iauxMax: w(11),
+            cbAuxOffset: w(12),
+            issMax: w(13),
+            cbSsOffset: w(14),
+            issExtMax: w(15),
+            cbSsExtOffset: w(16),
+            ifdMax: w(17),
+            cbFdOffset: w(18),
+            crfd: w(19),
+            cbRfdOffset: w(20),
+            iextMax: w(21),
+            cbExtOffset: w(22),
+        }
+    }
+}
+
+// File descriptor
+#[derive(Debug)]
+pub struct FDR {
+    pub adr: u32,
+    pub rss: i32,
+    pub issBase: i32,
+    pub cbSs: i32,
+    pub isymBase: i32,
+    pub csym: i32,
+    pub ilineBase: i32,
+    pub cline: i32,
+    pub ioptBase: i32,
+    pub copt: i32,
+    pub ipdFirst: u16,
+    pub cpd: u16,
+    pub iauxBase: i32,
+    pub caux: i32,
+    pub rfdBase: i32,
+    pub crfd: i32,
+    pub lang: u32,
+    pub fMerge: bool,
+    pub fReadin: bool,
+    pub fBigendian: bool,
+    pub glevel: u32,
+    pub cbLineOffset: i32,
+    pub cbLine: i32,
+}
+
+impl FDR {
+    fn read(r: Reader) -> FDR {
+        let w = |off: usize| r.i32(off);
+        let bits = r.u32(0x3C);
+        FDR {
+            adr: r.u32(0x0),
+            rss: w(0x4),
+            issBase: w(0x8),
+            cbSs: w(0xC),
+            isymBase: w(0x10),
+            csym: w(0x14),
+            ilineBase: w(0x18),
+            cline: w(0x1C),
+            ioptBase: w(0x20),
+            copt: w(0x24),
+            ipdFirst: r.u16(0x28),
+            cpd: r.u16(0x2A),
+            iauxBase: w(0x2C),
+            caux: w(0x30),
+            rfdBase: w(0x34),
+            crfd: w(0x38),
+            lang: r.get_bits(bits, 32, 0, 5),
+            fMerge: r.get_bits(bits, 32, 5, 1) != 0,
+            fReadin: r.get_bits(bits, 32, 6, 1) != 0,
+            fBigendian: r.get_bits(bits, 32, 7, 1) != 0,
+            glevel: r.get_bits(bits, 32, 8, 2),
+            cbLineOffset: w(0x40),
+            cbLine: w(0x44),
+        }
+    }
+}
+
+// Procedure descriptor
+#[derive(Debug)]
+pub struct PDR {
+    pub adr: u32,
+    pub isym: i32,
+    pub iline: i32,
+    pub regmask: u32,
+    pub regoffset: i32,
+    pub iopt: i32,
+    pub fregmask: u32,
+    pub fregoffset: i32,
+    pub frameoffset: i32,
+    pub framereg: i16,
+    pub pcreg: i16,
+    pub lnLow: i32,
+    pub lnHigh: i32,
+    pub cbLineOffset: u32,
+}
+
+impl PDR {
+    fn read(r: Reader) -> PDR {
+        PDR {
+            adr: r.u32(0x0),
+            isym: r.i32(0x4),
+            iline: r.i32(0x8),
+            regmask: r.u32(0xC),
+            regoffset: r.i32(0x10),
+            iopt: r.i32(0x14),
+            fregmask: r.u32(0x18),
+            fregoffset: r.i32(0x1C),
+            frameoffset: r.i32(0x20),
+            framereg: r.i16(0x24),
+            pcreg: r.i16(0x26),
+            lnLow: r.i32(0x28),
+            lnHigh: r.i32(0x2C),
+            cbLineOffset: r.u32(0x30),
+        }
+    }
+}
+
+// Local symbol
+#[derive(Debug)]
+pub struct SYMR {
+    pub iss: i32,
+    pub value: i32,
+    pub st: u32,
+    pub sc: u32,
+    pub index: u32,
+}
+
+impl SYMR {
+    fn read(r: Reader) -> SYMR {
+        let bits = r.u32(8);
+        SYMR {
+            iss: r.i32(0),
+            value: r.i32(4),
+            st: r.get_bits(bits, 32, 0, 6),
+            sc: r.get_bits(bits, 32, 6, 5),
+            index: r.get_bits(bits, 32, 12, 20),
+        }
+    }
+}
+
+// External symbol
+#[derive(Debug)]
+pub struct EXTR {
+    pub jmptbl: bool,
+    pub cobol_main: bool,
+    pub weakext: bool,
+    pub ifd: i16,
+    pub asym: SYMR,
+}
+
+impl EXTR {
+    fn read(r: Reader) -> EXTR {
+        let bits = r.u16(0) as u32;
+        EXTR {
+            jmptbl: r.get_bits(bits, 16, 0, 1) != 0,
+            cobol_main: r.get_bits(bits, 16, 1, 1) != 0,
+            weakext: r.get_bits(bits, 16, 2, 1) != 0,
+            ifd: r.i16(2),
+            asym: SYMR::read(Reader {
+                bytes: &r.bytes[4..],
+                ..r
+            }),
+        }
+    }
+}
+
+// Dense number
+#[derive(Debug)]
+pub struct DNR {
+    pub rfd: u32,
+    pub index: u32,
+}
+
+impl DNR {
+    fn read(r: Reader) -> DNR {
+        DNR {
+            rfd: r.u32(0),
+            index: r.u32(4),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SymbolTable {
+    pub hdr: HDRR,
+    pub fdrs: Vec<FDR>,
+    pub pdrs: Vec<PDR>,
+    pub symrs: Vec<SYMR>,
+    pub extrs: Vec<EXTR>,
+    pub dnrs: Vec<DNR>,
+    ss: Vec<u8>,
+    ssext: Vec<u8>,
+}
+
+fn read_table<T>(
+    bytes: &[u8],
+    big_endian: bool,
+    base: usize,
+    offset: i32,
+    count: i32,
+    length: usize,
+    read: fn(Reader) -> T,
+) -> Option<Vec<T>> {
+    if count <= 0 {
+        return Some(Vec::new());
+    }
+    let start = usize::try_from(offset).ok()?.checked_sub(base)?;
+    let end = start.checked_add(count as usize * length)?;
+    Some(
+        bytes
+            .get(start..end)?
+            .chunks_exact(length)
+            .map(|bytes| read(Reader { bytes, big_endian }))
+            .collect(),
+    )
+}
+
+fn read_string(strings: &[u8], iss: i32) -> Option<&str> {
+    if iss < 0 || iss as usize >= strings.len() {
+        return None;
+    }
+    let s = &strings[iss as usize..];
+    let end = s.iter().position(|c| *c == 0).unwrap_or(s.len());
+    std::str::from_utf8(&s[..end]).ok()
+}
+
+impl SymbolTable {
+    // Reads a symbolic header and the tables it describes. Offsets in the header are relative to
+    // `base` bytes before the start of `bytes`, so a header embedded in a larger file can be read
+    // from a slice of that file. The byte order is that of the magic number.
+    pub fn read(bytes: &[u8], base: usize) -> Option<SymbolTable> {
+        if bytes.len() < HDRR_LENGTH {
+            return None;
+        }
+        let magic = [bytes[0], bytes[1]];
+        let big_endian = if u16::from_be_bytes(magic) == MAGIC_SYM {
+            true
+        } else if u16::from_le_bytes(magic) == MAGIC_SYM {
+            false
+        } else {
+            return None;
+        };
+        let hdr = HDRR::read(Reader { bytes, big_endian });
+
+        let fdrs = read_table(
+            bytes,
+            big_endian,
+            base,
+            hdr.cbFdOffset,
+            hdr.ifdMax,
+            FDR_LENGTH,
+            FDR::read,
+        )?;
+        let pdrs = read_table(
+            bytes,
+            big_endian,
+            base,
+            hdr.cbPdOffset,
+            hdr.ipdMax,
+            PDR_LENGTH,
+            PDR::read,
+        )?;
+        let symrs = read_table(
+            bytes,
+            big_endian,
+            base,
+            hdr.cbSymOffset,
+            hdr.isymMax,
+            SYMR_LENGTH,
+            SYMR::read,
+        )?;
+        let extrs = read_table(
+            bytes,
+            big_endian,
+            base,
+            hdr.cbExtOffset,
+            hdr.iextMax,
+            EXTR_LENGTH,
+            EXTR::read,
+        )?;
+        let dnrs = read_table(
+            bytes,
+            big_endian,
+            base,
+            hdr.cbDnOffset,
+            hdr.idnMax,
+            DNR_LENGTH,
+            DNR::read,
+        )?;
+        let ss = read_table(
+            bytes,
+            big_endian,
+            base,
+            hdr.cbSsOffset,
+            hdr.issMax,
+            1,
+            |r| r.bytes[0],
+        )?;
+        let ssext = read_table(
+            bytes,
+            big_endian,
+            base,
+            hdr.cbSsExtOffset,
+            hdr.issExtMax,
+            1,
+            |r| r.bytes[0],
+        )?;
+
+        Some(SymbolTable {
+            hdr,
+            fdrs,
+            pdrs,
+            symrs,
+            extrs,
+            dnrs,
+            ss,
+            ssext,
+        })
+    }
+
+    // Reads a standalone .T file
+    pub fn parse(bytes: &[u8]) -> Option<SymbolTable> {
+        SymbolTable::read(bytes, 0)
+    }
+
+    // Reads the .mdebug section of an ELF object, shared object or executable. IRIX writes the
+    // header's offsets relative to the start of the file, so they are rebased onto the section.
+    pub fn parse_elf(bytes: &[u8]) -> Option<SymbolTable> {
+        let elf = Elf::parse(bytes)?;
+        let mdebug = elf
+            .sections
+            .iter()
+            .find(|s| s.sh_type == elf::SHT_MIPS_DEBUG || s.name == ".mdebug")?;
+        SymbolTable::read(elf.section_data(mdebug)?, mdebug.sh_offset as usize)
+    }
+
+    // Reads the symbolic header of an ECOFF object, which f_symptr points to
+    pub fn parse_ecoff(bytes: &[u8]) -> Option<SymbolTable> {
+        let (symbolic, base) = Ecoff::parse(bytes)?.symbolic_header()?;
+        SymbolTable::read(symbolic, base)
+    }
+
+    // Reads a symbol table from any of the containers it may be found in
+    pub fn from_file(bytes: &[u8]) -> Option<SymbolTable> {
+        if elf::is_elf(bytes) {
+            SymbolTable::parse_elf(bytes)
+        } else if ecoff::is_ecoff(bytes) {
+            SymbolTable::parse_ecoff(bytes)
+        } else {
+            SymbolTable::parse(bytes)
+        }
+    }
+
+    pub fn local_name(&self, ifd: usize, isym: i32) -> Option<&str> {
+        let fd = self.fdrs.get(ifd)?;
+        let isym = usize::try_from(fd.isymBase.checked_add(isym)?).ok()?;
+        let sym = self.symrs.get(isym)?;
+        let strings = self.ss.get(fd.issBase as usize..)?;
+        read_string(strings, sym.iss)
+    }
+
+    pub fn external_name(&self, iext: usize) -> Option<&str> {
+        read_string(&self.ssext, self.extrs.get(iext)?.asym.iss)
+    }
+
+    // Name of the symbol a dense number (binasm's symno) refers to
+    pub fn dense_name(&self, idn: u32) -> Option<&str> {
+        let dn = self.dnrs.get(idn as usize)?;
+        if dn.rfd == ST_EXTIFD {
+            self.external_name(dn.index as usize)
+        } else {
+            self.local_name(dn.rfd as usize, dn.index as i32)
+        }
+    }
+
+    // File that defines the symbol a dense number refers to
+    fn dense_file(&self, idn: u32) -> Option<usize> {
+        let dn = self.dnrs.get(idn as usize)?;
+        if dn.rfd == ST_EXTIFD {
+            let ifd = self.extrs.get(dn.index as usize)?.ifd;
+            (ifd >= 0).then_some(ifd as usize)
+        } else {
+            Some(dn.rfd as usize)
+        }
+    }
+
+    // Procedure descriptor of the procedure a dense number refers to, matched by name among the
+    // defining file's procedures
+    pub fn procedure(&self, idn: u32) -> Option<&PDR> {
+        let name = self.dense_name(idn)?;
+        let ifd = self.dense_file(idn)?;
+        let fd = self.fdrs.get(ifd)?;
+        let first = fd.ipdFirst as usize;
+        self.pdrs
+            .get(first..first + fd.cpd as usize)?
+            .iter()
+            .find(|pd| self.local_name(ifd, pd.isym) == Some(name))
+    }
+}
+
+impl fmt::Display for PDR {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "adr 0x{:08X} isym {} frame {} ${} ${} mask 0x{:08X} {} fmask 0x{:08X} {}",
+            self.adr,
+            self.isym,
+            self.frameoffset,
+            self.framereg,
+            self.pcreg,
+            self.regmask,
+            self.regoffset,
+            self.fregmask,
+            self.fregoffset
+        )
+    }
+}
+
+// Prints the file and procedure tables
+pub fn print_symbol_table(st: &SymbolTable) {
+    println!(
+        "magic 0x{:04X} vstamp 0x{:04X}: {} files, {} procedures, {} symbols, {} externals, {} dense numbers",
+        st.hdr.magic,
+        st.hdr.vstamp,
+        st.fdrs.len(),
+        st.pdrs.len(),
+        st.symrs.len(),
+        st.extrs.len(),
+        st.dnrs.len()
+    );
+    for (ifd, fd) in st.fdrs.iter().enumerate() {
+        let name = st
+            .ss
+            .get(fd.issBase as usize..)
+            .and_then(|s| read_string(s, fd.rss))
+            .unwrap_or("?");
+        println!("file {ifd}: {name}");
+        let first = fd.ipdFirst as usize;
+        let pdrs = st
+            .pdrs
+            .get(first..first + fd.cpd as usize)
+            .unwrap_or_default();
+        for pd in pdrs {
+            let name = st.local_name(ifd, pd.isym).unwrap_or("?");
+            println!("\t{name}: {pd}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::symbol_table;
+    use super::*;
+
+    #[test]
+    fn reads_both_byte_orders() {
+        for big_endian in [true, false] {
+            let st = SymbolTable::parse(&symbol_table(big_endian, 0)).unwrap();
+            assert_eq!(st.fdrs[0].fBigendian, big_endian);
+            assert_eq!(st.dense_name(1), Some("main"));
+            assert_eq!(st.dense_name(2), Some("helper"));
+            assert_eq!(st.dense_name(3), Some("puts"));
+            assert_eq!(st.dense_name(4), None);
+
+            let main = st.procedure(1).unwrap();
+            assert_eq!(
+                (main.regmask, main.regoffset, main.frameoffset),
+                (0x8000_0000, -4, 24)
+            );
+            assert_eq!(st.procedure(2).unwrap().isym, 2);
+            // Undefined externals have no procedure descriptor
+            assert!(st.procedure(3).is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_tables_past_the_end() {
+        let bytes = symbol_table(true, 0);
+        assert!(SymbolTable::parse(&bytes[..bytes.len() - 1]).is_none());
+        // Offsets that point before the start of the data
+        assert!(SymbolTable::read(&bytes, 0x100).is_none());
+    }
+}